@@ -0,0 +1,282 @@
+use crate::app::image_utils::srgb_to_lab;
+use crate::app::mosaic::{CellColors, grid_size, match_distance, pixel_color};
+use crate::app::traits::{CatalogStore, ImageIo};
+use crate::domain::{
+    CatalogStats, ColorBin, ColorGap, ColorSpace, GapReport, GapSpec, MatchMetric, OutputOptions,
+};
+use crate::error::{AppError, AppResult};
+use image::{Rgb, RgbImage};
+use std::collections::HashMap;
+
+const GAP_LEVELS: u8 = 6;
+const SWATCH_SIZE: u32 = 48;
+
+pub fn catalog_stats<C: CatalogStore>(catalog_store: &C, levels: u8) -> AppResult<CatalogStats> {
+    if levels == 0 {
+        return Err(AppError::InvalidInput(
+            "histogram levels must be greater than zero".to_string(),
+        ));
+    }
+
+    let catalog = catalog_store.load()?;
+    let mut counts: HashMap<usize, usize> = HashMap::new();
+    let mut sums = [0u64; 3];
+    let mut linear_tiles = 0;
+    for tile in &catalog.tiles {
+        *counts.entry(bin_index(tile.avg_color, levels)).or_default() += 1;
+        if tile.metadata.color_space == Some(ColorSpace::Linear) {
+            linear_tiles += 1;
+        }
+        for (sum, channel) in sums.iter_mut().zip(tile.avg_color) {
+            *sum += channel as u64;
+        }
+    }
+
+    let tile_count = catalog.tiles.len();
+    let divisor = (tile_count as u64).max(1);
+    let mut bins: Vec<ColorBin> = counts
+        .into_iter()
+        .map(|(index, count)| ColorBin {
+            color: bin_center(index, levels),
+            count,
+        })
+        .collect();
+    bins.sort_by(|a, b| b.count.cmp(&a.count).then(a.color.cmp(&b.color)));
+
+    let brightness: Vec<f64> = catalog
+        .tiles
+        .iter()
+        .filter_map(|t| t.metadata.brightness)
+        .collect();
+    let saturation: Vec<f64> = catalog
+        .tiles
+        .iter()
+        .filter_map(|t| t.metadata.saturation)
+        .collect();
+
+    Ok(CatalogStats {
+        tile_count,
+        levels,
+        mean_color: sums.map(|sum| (sum / divisor) as u8),
+        mean_brightness: mean(&brightness),
+        mean_saturation: mean(&saturation),
+        color_spaces: [
+            (ColorSpace::Srgb, tile_count - linear_tiles),
+            (ColorSpace::Linear, linear_tiles),
+        ]
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .collect(),
+        bins,
+        total_bins: (levels as usize).pow(3),
+    })
+}
+
+/// Reports which colors of `spec.input` the catalog cannot match closely,
+/// reading cells and ranking tiles as mosaic generation does. Tiles are
+/// compared by their stored average color.
+pub fn find_gaps<C: CatalogStore, I: ImageIo>(
+    catalog_store: &C,
+    image_io: &I,
+    spec: &GapSpec,
+) -> AppResult<GapReport> {
+    let catalog = catalog_store.load()?;
+    if catalog.tiles.is_empty() {
+        return Err(AppError::InvalidInput("catalog is empty".to_string()));
+    }
+    let palette = Palette::new(catalog.tiles.iter().map(|tile| tile.avg_color), spec.metric);
+    let colors = CellColors {
+        matte: spec.matte,
+        space: spec.color_space,
+        transparent: spec.preserve_transparency,
+    };
+
+    let input = image_io.read(&spec.input)?;
+    let (grid_width, grid_height) = grid_size(&input, spec.tile_size)?;
+
+    #[derive(Default)]
+    struct Accumulator {
+        sums: [u64; 3],
+        cells: usize,
+        error: f32,
+    }
+
+    let mut bins: HashMap<usize, Accumulator> = HashMap::new();
+    let mut total_cells = 0;
+    let mut total_error = 0.0;
+    let mut poorly_served = 0;
+    let size = spec.tile_size;
+    for tile_y in 0..grid_height {
+        for tile_x in 0..grid_width {
+            let rect = (tile_x * size, tile_y * size, size, size);
+            let Some(target) = pixel_color(&input, colors, rect) else {
+                continue;
+            };
+            let (_, distance) = palette.nearest(target);
+            let error = distance.sqrt();
+            total_cells += 1;
+            total_error += error;
+            if error <= spec.max_error {
+                continue;
+            }
+
+            poorly_served += 1;
+            let bin = bins.entry(bin_index(target, GAP_LEVELS)).or_default();
+            for (sum, channel) in bin.sums.iter_mut().zip(target) {
+                *sum += channel as u64;
+            }
+            bin.cells += 1;
+            bin.error += error;
+        }
+    }
+
+    let mut gaps: Vec<ColorGap> = bins
+        .into_values()
+        .map(|bin| {
+            let target = bin.sums.map(|sum| (sum / bin.cells as u64) as u8);
+            ColorGap {
+                target,
+                nearest: palette.nearest(target).0,
+                cells: bin.cells,
+                mean_error: bin.error / bin.cells as f32,
+            }
+        })
+        .collect();
+    gaps.sort_by(|a, b| {
+        b.cells
+            .cmp(&a.cells)
+            .then(b.mean_error.total_cmp(&a.mean_error))
+    });
+    gaps.truncate(spec.limit);
+
+    if let Some(path) = &spec.swatches
+        && !gaps.is_empty()
+    {
+        image_io.write_rgb(path, &render_swatches(&gaps), &OutputOptions::default())?;
+    }
+
+    let swatches = spec.swatches.clone().filter(|_| !gaps.is_empty());
+    Ok(GapReport {
+        total_cells,
+        poorly_served,
+        mean_error: total_error / total_cells.max(1) as f32,
+        gaps,
+        swatches,
+    })
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Catalog colors, with their Lab values when tiles are ranked by Lab.
+struct Palette {
+    colors: Vec<[u8; 3]>,
+    labs: Option<Vec<[f32; 3]>>,
+}
+
+impl Palette {
+    fn new(colors: impl Iterator<Item = [u8; 3]>, metric: MatchMetric) -> Self {
+        let colors: Vec<[u8; 3]> = colors.collect();
+        let labs = (metric == MatchMetric::Lab)
+            .then(|| colors.iter().map(|color| srgb_to_lab(*color)).collect());
+        Palette { colors, labs }
+    }
+
+    fn nearest(&self, target: [u8; 3]) -> ([u8; 3], f32) {
+        let target_lab = self.labs.as_ref().map(|_| srgb_to_lab(target));
+        self.colors
+            .iter()
+            .enumerate()
+            .map(|(index, color)| {
+                let lab = self.labs.as_ref().map(|labs| labs[index]);
+                (*color, match_distance((*color, lab), (target, target_lab)))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("palette is not empty")
+    }
+}
+
+fn bin_index(color: [u8; 3], levels: u8) -> usize {
+    let levels = levels as usize;
+    let quantize = |channel: u8| channel as usize * levels / 256;
+    (quantize(color[0]) * levels + quantize(color[1])) * levels + quantize(color[2])
+}
+
+fn bin_center(index: usize, levels: u8) -> [u8; 3] {
+    let levels = levels as usize;
+    let center = |level: usize| ((level * 256 + 128) / levels).min(255) as u8;
+    [
+        center(index / (levels * levels)),
+        center(index / levels % levels),
+        center(index % levels),
+    ]
+}
+
+/// One square per gap: the target color on the left, the closest tile color on the right.
+fn render_swatches(gaps: &[ColorGap]) -> RgbImage {
+    let mut image = RgbImage::new(SWATCH_SIZE * gaps.len() as u32, SWATCH_SIZE);
+    for (index, gap) in gaps.iter().enumerate() {
+        let offset = index as u32 * SWATCH_SIZE;
+        for y in 0..SWATCH_SIZE {
+            for x in 0..SWATCH_SIZE {
+                let color = if x < SWATCH_SIZE / 2 {
+                    gap.target
+                } else {
+                    gap.nearest
+                };
+                image.put_pixel(offset + x, y, Rgb(color));
+            }
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::testing::{tile, MemoryCatalog, MemoryImages};
+    use std::path::PathBuf;
+
+    fn gaps(limit: usize) -> (GapReport, MemoryImages) {
+        // Left half black, which the catalog covers; right half white, which it does not.
+        let input = RgbImage::from_fn(8, 4, |x, _| Rgb([if x < 4 { 0 } else { 255 }; 3]));
+        let images = MemoryImages::default().with("in.png", input);
+        let catalog = MemoryCatalog::new(vec![tile("black", "black.png", [0, 0, 0])]);
+        let spec = GapSpec {
+            input: PathBuf::from("in.png"),
+            tile_size: 4,
+            max_error: 30.0,
+            limit,
+            swatches: Some(PathBuf::from("swatches.png")),
+            color_space: ColorSpace::Srgb,
+            metric: MatchMetric::Rgb,
+            matte: [0, 0, 0],
+            preserve_transparency: false,
+        };
+        (find_gaps(&catalog, &images, &spec).unwrap(), images)
+    }
+
+    #[test]
+    fn gaps_report_poorly_served_colors_with_swatches() {
+        let (report, images) = gaps(10);
+        assert_eq!((report.total_cells, report.poorly_served), (2, 1));
+        assert_eq!(report.gaps.len(), 1);
+        assert_eq!(report.gaps[0].target, [255; 3]);
+        assert_eq!(report.gaps[0].nearest, [0; 3]);
+        assert_eq!(report.swatches, Some(PathBuf::from("swatches.png")));
+        assert!(images.get("swatches.png").is_some());
+    }
+
+    #[test]
+    fn no_swatches_are_named_when_no_gaps_are_listed() {
+        let (report, images) = gaps(0);
+        assert_eq!(report.poorly_served, 1);
+        assert!(report.gaps.is_empty());
+        assert_eq!(report.swatches, None);
+        assert!(images.get("swatches.png").is_none());
+    }
+}
@@ -1,39 +1,87 @@
-use crate::app::image_utils::average_color;
+use crate::app::image_utils::{average_color, hamming_distance, image_stats, perceptual_hash};
+use crate::app::scan::collect_image_paths;
 use crate::app::traits::{CatalogStore, ImageIo};
-use crate::domain::{Catalog, Tile};
+use crate::domain::{
+    Catalog, CatalogBundle, HashKind, ImportOptions, ImportReport, OutputOptions, PerceptualHash,
+    QualityThresholds, SkippedImage, Tile, TileMetadata,
+};
 use crate::error::{AppError, AppResult};
+use chrono::Utc;
+use image::{imageops, DynamicImage};
+use std::collections::HashMap;
+use std::fs;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use std::time::UNIX_EPOCH;
 
 pub fn add_tiles<C: CatalogStore, I: ImageIo>(
     catalog_store: &C,
     image_io: &I,
     path: &Path,
-) -> AppResult<Vec<Tile>> {
+    options: &ImportOptions,
+) -> AppResult<ImportReport> {
     let mut catalog = catalog_store.load()?;
-    let image_paths = collect_image_paths(path)?;
+    let image_paths = collect_image_paths(image_io, path, &options.scan)?;
 
     if image_paths.is_empty() {
         return Err(AppError::InvalidInput("no image files found".to_string()));
     }
 
-    let mut added = Vec::new();
+    let mut report = ImportReport::default();
     for image_path in image_paths {
+        let stored_path = if catalog.root.is_some() {
+            catalog.storage_path(&std::path::absolute(&image_path)?)
+        } else {
+            image_path.clone()
+        };
+        let id = tile_id_for_path(&stored_path);
+        // Known files are skipped before they are decoded, hashed or measured.
+        if catalog.contains(&id, &stored_path) {
+            continue;
+        }
+
         let image = image_io.read(&image_path)?;
-        let avg_color = average_color(&image);
         let tile = Tile {
-            id: tile_id_for_path(&image_path),
-            path: image_path.clone(),
-            avg_color,
+            id,
+            path: stored_path,
+            avg_color: average_color(&image, options.color_space),
+            thumbnail: None,
+            phash: Some(PerceptualHash {
+                kind: options.hash_kind,
+                value: perceptual_hash(&image, options.hash_kind),
+            }),
+            tags: options.tags.clone(),
+            metadata: TileMetadata {
+                imported_at: Some(Utc::now().timestamp()),
+                color_space: Some(options.color_space),
+                ..describe_image(image_io, &image_path, &image)?
+            },
         };
 
+        if let Some(reason) = quality_rejection(&tile.metadata, &options.quality) {
+            report.skipped.push(SkippedImage {
+                path: image_path,
+                reason,
+            });
+            continue;
+        }
+
+        if let (Some(threshold), Some(hash)) = (options.near_duplicate_threshold, tile.phash)
+            && let Some(existing) = find_similar(&catalog, hash, threshold)
+        {
+            report.skipped.push(SkippedImage {
+                path: image_path,
+                reason: format!("near-duplicate of {}", existing.id),
+            });
+            continue;
+        }
+
         if catalog.add_tile(tile.clone()) {
-            added.push(tile);
+            report.added.push(tile);
         }
     }
 
     catalog_store.save(&catalog)?;
-    Ok(added)
+    Ok(report)
 }
 
 pub fn list_tiles<C: CatalogStore>(catalog_store: &C) -> AppResult<Catalog> {
@@ -49,41 +97,279 @@ pub fn remove_tile<C: CatalogStore>(catalog_store: &C, id: &str) -> AppResult<Ti
     Ok(removed)
 }
 
-fn collect_image_paths(path: &Path) -> AppResult<Vec<PathBuf>> {
-    if path.is_file() {
-        if is_image_path(path) {
-            return Ok(vec![path.to_path_buf()]);
-        }
+pub fn set_root<C: CatalogStore>(catalog_store: &C, root: &Path) -> AppResult<(PathBuf, usize)> {
+    if !root.is_dir() {
         return Err(AppError::InvalidInput(
-            "path is not an image file".to_string(),
+            "library root is not a directory".to_string(),
         ));
     }
 
-    if !path.is_dir() {
-        return Err(AppError::InvalidInput(
-            "path is not a file or directory".to_string(),
-        ));
+    let root = std::path::absolute(root)?;
+    let mut catalog = catalog_store.load()?;
+    if catalog.root.is_none() {
+        for tile in &mut catalog.tiles {
+            tile.path = std::path::absolute(&tile.path)?;
+        }
+    }
+    let relative = catalog.set_root(root.clone());
+    catalog_store.save(&catalog)?;
+    Ok((root, relative))
+}
+
+pub fn relocate<C: CatalogStore>(
+    catalog_store: &C,
+    old_root: &Path,
+    new_root: &Path,
+) -> AppResult<usize> {
+    let old_root = std::path::absolute(old_root)?;
+    let new_root = std::path::absolute(new_root)?;
+    let mut catalog = catalog_store.load()?;
+    let moved = catalog.relocate(&old_root, &new_root);
+    catalog_store.save(&catalog)?;
+    Ok(moved)
+}
+
+pub fn export_catalog<C: CatalogStore, I: ImageIo>(
+    catalog_store: &C,
+    image_io: &I,
+    thumbnail_size: Option<u32>,
+) -> AppResult<CatalogBundle> {
+    let catalog = catalog_store.load()?;
+    let mut thumbnails = Vec::new();
+
+    if let Some(size) = thumbnail_size {
+        if size == 0 {
+            return Err(AppError::InvalidInput(
+                "thumbnail size must be greater than zero".to_string(),
+            ));
+        }
+        for tile in &catalog.tiles {
+            let source = catalog.resolve_path(&tile.path);
+            let source = match &tile.thumbnail {
+                Some(thumbnail) if !source.exists() => thumbnail.clone(),
+                _ => source,
+            };
+            let image = image_io.read(&source)?;
+            let thumbnail = image.resize(size, size, imageops::FilterType::Triangle);
+            thumbnails.push((tile.id.clone(), thumbnail.to_rgb8()));
+        }
+    }
+
+    let mut catalog = catalog;
+    for tile in &mut catalog.tiles {
+        tile.thumbnail = None;
+    }
+
+    Ok(CatalogBundle {
+        catalog,
+        thumbnails,
+    })
+}
+
+pub fn import_catalog<C: CatalogStore, I: ImageIo>(
+    catalog_store: &C,
+    image_io: &I,
+    bundle: CatalogBundle,
+    root: Option<&Path>,
+) -> AppResult<Vec<Tile>> {
+    let mut catalog = catalog_store.load()?;
+    let mut source = bundle.catalog;
+    if let Some(root) = root {
+        source.root = Some(std::path::absolute(root)?);
+    }
+
+    if catalog.root.is_none() && catalog.tiles.is_empty() {
+        catalog.root = source.root.clone();
+    }
+
+    let thumbnail_dir = catalog_store.thumbnail_dir();
+    let mut added = Vec::new();
+    for tile in &source.tiles {
+        let path = catalog.storage_path(&source.resolve_path(&tile.path));
+        let mut tile = Tile {
+            path,
+            thumbnail: None,
+            ..tile.clone()
+        };
+
+        let thumbnail = bundle.thumbnails.iter().find(|(id, _)| *id == tile.id);
+        if thumbnail.is_some() {
+            tile.thumbnail = Some(thumbnail_dir.join(format!("{}.png", tile.id)));
+        }
+
+        if catalog.add_tile(tile.clone()) {
+            if let (Some((_, image)), Some(thumbnail_path)) = (thumbnail, &tile.thumbnail) {
+                image_io.write_rgb(thumbnail_path, image, &OutputOptions::default())?;
+            }
+            added.push(tile);
+        }
     }
 
-    let mut images = Vec::new();
-    for entry in WalkDir::new(path).into_iter().filter_map(Result::ok) {
-        let entry_path = entry.path();
-        if entry_path.is_file() && is_image_path(entry_path) {
-            images.push(entry_path.to_path_buf());
+    catalog_store.save(&catalog)?;
+    Ok(added)
+}
+
+pub fn tag_tile<C: CatalogStore>(
+    catalog_store: &C,
+    id: &str,
+    add: &[String],
+    remove: &[String],
+) -> AppResult<Tile> {
+    let mut catalog = catalog_store.load()?;
+    let tile = catalog
+        .find_by_id_mut(id)
+        .ok_or_else(|| AppError::CatalogNotFound(id.to_string()))?;
+
+    tile.tags.retain(|tag| !remove.contains(tag));
+    for tag in add {
+        if !tile.tags.contains(tag) {
+            tile.tags.push(tag.clone());
         }
     }
 
-    Ok(images)
+    let tile = tile.clone();
+    catalog_store.save(&catalog)?;
+    Ok(tile)
+}
+
+/// Groups catalog tiles whose perceptual hashes are within `threshold` bits of
+/// each other. Tiles without a stored hash of `kind` are hashed in memory; the
+/// catalog is left as it is.
+pub fn find_near_duplicates<C: CatalogStore, I: ImageIo>(
+    catalog_store: &C,
+    image_io: &I,
+    kind: HashKind,
+    threshold: u32,
+) -> AppResult<Vec<Vec<Tile>>> {
+    let catalog = catalog_store.load()?;
+    let hashes = catalog
+        .tiles
+        .iter()
+        .map(|tile| match tile.phash {
+            Some(hash) if hash.kind == kind => Ok(hash.value),
+            _ => {
+                let image = image_io.read(&catalog.resolve_path(&tile.path))?;
+                Ok(perceptual_hash(&image, kind))
+            }
+        })
+        .collect::<AppResult<Vec<u64>>>()?;
+    let mut parents: Vec<usize> = (0..hashes.len()).collect();
+    for a in 0..hashes.len() {
+        for b in (a + 1)..hashes.len() {
+            if hamming_distance(hashes[a], hashes[b]) <= threshold {
+                let root_a = find_root(&mut parents, a);
+                let root_b = find_root(&mut parents, b);
+                parents[root_b] = root_a;
+            }
+        }
+    }
+
+    let mut clusters: Vec<Vec<Tile>> = Vec::new();
+    let mut cluster_by_root: HashMap<usize, usize> = HashMap::new();
+    for index in 0..hashes.len() {
+        let root = find_root(&mut parents, index);
+        let cluster = *cluster_by_root.entry(root).or_insert_with(|| {
+            clusters.push(Vec::new());
+            clusters.len() - 1
+        });
+        clusters[cluster].push(catalog.tiles[index].clone());
+    }
+
+    clusters.retain(|cluster| cluster.len() > 1);
+    Ok(clusters)
+}
+
+fn quality_rejection(metadata: &TileMetadata, thresholds: &QualityThresholds) -> Option<String> {
+    let width = metadata.width.unwrap_or_default();
+    let height = metadata.height.unwrap_or_default();
+    if thresholds.min_width.is_some_and(|min| width < min)
+        || thresholds.min_height.is_some_and(|min| height < min)
+    {
+        return Some(format!("too small ({width}x{height})"));
+    }
+
+    let underexposed = metadata.underexposed.unwrap_or_default();
+    if let Some(max) = thresholds.max_underexposed
+        && underexposed > max
+    {
+        return Some(format!("underexposed ({:.0}% near-black)", underexposed * 100.0));
+    }
+
+    let overexposed = metadata.overexposed.unwrap_or_default();
+    if let Some(max) = thresholds.max_overexposed
+        && overexposed > max
+    {
+        return Some(format!("overexposed ({:.0}% near-white)", overexposed * 100.0));
+    }
+
+    let sharpness = metadata.sharpness.unwrap_or_default();
+    if let Some(min) = thresholds.min_sharpness
+        && sharpness < min
+    {
+        return Some(format!("blurry (sharpness {sharpness:.1} < {min})"));
+    }
+
+    None
+}
+
+fn find_similar(catalog: &Catalog, hash: PerceptualHash, threshold: u32) -> Option<&Tile> {
+    catalog.tiles.iter().find(|tile| {
+        tile.phash
+            .is_some_and(|h| h.kind == hash.kind && hamming_distance(h.value, hash.value) <= threshold)
+    })
+}
+
+fn find_root(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+fn describe_image<I: ImageIo>(
+    image_io: &I,
+    path: &Path,
+    image: &DynamicImage,
+) -> AppResult<TileMetadata> {
+    let file = fs::metadata(path)?;
+    let bytes = fs::read(path)?;
+    let stats = image_stats(image);
+    let exif = image_io.read_exif(path)?;
+
+    Ok(TileMetadata {
+        width: Some(image.width()),
+        height: Some(image.height()),
+        imported_at: None,
+        file_size: Some(file.len()),
+        modified: modified_timestamp(&file),
+        content_hash: Some(blake3::hash(&bytes).to_hex().to_string()),
+        brightness: Some(round_stat(stats.brightness)),
+        contrast: Some(round_stat(stats.contrast)),
+        saturation: Some(round_stat(stats.saturation)),
+        color_variance: Some(round_stat(stats.color_variance)),
+        sharpness: Some(round_stat(stats.sharpness)),
+        underexposed: Some(round_stat(stats.underexposed)),
+        overexposed: Some(round_stat(stats.overexposed)),
+        dominant_colors: stats.dominant_colors,
+        captured_at: exif.captured_at,
+        orientation: exif.orientation,
+        color_space: None,
+    })
+}
+
+/// Keeps stored statistics readable; four decimals is plenty for filtering.
+fn round_stat(value: f32) -> f64 {
+    (value as f64 * 10_000.0).round() / 10_000.0
 }
 
-fn is_image_path(path: &Path) -> bool {
-    path.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| matches!(ext.to_lowercase().as_str(), "png" | "jpg" | "jpeg" | "bmp" | "gif"))
-        .unwrap_or(false)
+fn modified_timestamp(file: &fs::Metadata) -> Option<i64> {
+    let modified = file.modified().ok()?;
+    let seconds = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    i64::try_from(seconds).ok()
 }
 
-fn tile_id_for_path(path: &Path) -> String {
+pub(crate) fn tile_id_for_path(path: &Path) -> String {
     let bytes = path.to_string_lossy();
     blake3::hash(bytes.as_bytes()).to_hex().to_string()
 }
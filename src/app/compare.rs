@@ -0,0 +1,425 @@
+use crate::app::image_utils::flatten;
+use crate::app::label::{chars_per_line, draw_text, line_height, wrap};
+use crate::app::mosaic::generate_mosaic;
+use crate::app::traits::{CatalogStore, ImageIo};
+use crate::domain::{
+    ColorSpace, CompareResult, CompareRun, CompareSpec, DetailSpec, DitherSpec, MosaicSpec,
+    OutputOptions, Variation, VariedSetting,
+};
+use crate::error::{AppError, AppResult};
+use image::{imageops, Rgb, RgbImage};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const THUMBNAIL_WIDTH: u32 = 320;
+const SHEET_PADDING: u32 = 12;
+const MAX_SHEET_COLUMNS: usize = 4;
+const LABEL_SCALE: u32 = 2;
+const SHEET_BACKGROUND: [u8; 3] = [255, 255, 255];
+const LABEL_COLOR: [u8; 3] = [32, 32, 32];
+
+/// Generates a mosaic for every combination of the varied settings, each
+/// next to the contact sheet and named after its settings, then writes the
+/// sheet. Every value is checked before the first run starts.
+pub fn compare_settings<C: CatalogStore, I: ImageIo>(
+    catalog_store: &C,
+    image_io: &I,
+    spec: &CompareSpec,
+) -> AppResult<CompareResult> {
+    if spec.variations.is_empty() {
+        return Err(AppError::InvalidInput(
+            "nothing to compare; vary at least one setting".to_string(),
+        ));
+    }
+    let sheet_path = &spec.base.output;
+    if image_io.is_pyramid(sheet_path) {
+        return Err(AppError::InvalidInput(
+            "the contact sheet must be a single image, not .dzi".to_string(),
+        ));
+    }
+    image_io.can_stream(sheet_path, &OutputOptions::default(), false)?;
+    // Runs are written next to the sheet, in its format.
+    image_io.can_stream(sheet_path, &spec.base.output_options, spec.base.preserve_transparency)?;
+
+    let runs = combinations(&spec.variations)
+        .into_iter()
+        .map(|values| {
+            let mut run = spec.base.clone();
+            let mut settings = Vec::new();
+            for (variation, value) in spec.variations.iter().zip(values) {
+                apply(&mut run, variation.setting, value)?;
+                settings.push(format!("{}={value}", variation.setting));
+            }
+            let label = settings.join(" ");
+            run.output = run_path(sheet_path, &label);
+            run.heatmap = spec.base.heatmap.as_deref().map(|path| run_path(path, &label));
+            Ok((label, run))
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+
+    let mut results = Vec::with_capacity(runs.len());
+    let mut thumbnails = Vec::with_capacity(runs.len());
+    for (label, run) in runs {
+        let started = Instant::now();
+        let result = generate_mosaic(catalog_store, image_io, &run)?;
+        let elapsed = started.elapsed();
+
+        let image = image_io.read(&result.output)?;
+        let filter = imageops::FilterType::Triangle;
+        let image = image.resize(THUMBNAIL_WIDTH, THUMBNAIL_WIDTH * 4, filter);
+        thumbnails.push(flatten(image, run.matte, ColorSpace::Srgb).to_rgb8());
+        results.push(CompareRun {
+            label,
+            result,
+            elapsed,
+        });
+    }
+
+    let columns = match spec.variations.as_slice() {
+        [.., last] if spec.variations.len() > 1 => last.values.len(),
+        _ => results.len().min(MAX_SHEET_COLUMNS),
+    };
+    let labels: Vec<&str> = results.iter().map(|run| run.label.as_str()).collect();
+    let sheet = contact_sheet(&thumbnails, &labels, columns);
+    image_io.write_rgb(sheet_path, &sheet, &OutputOptions::default())?;
+
+    Ok(CompareResult {
+        sheet: sheet_path.clone(),
+        runs: results,
+    })
+}
+
+/// Every combination of one value per variation, the last varying fastest.
+fn combinations(variations: &[Variation]) -> Vec<Vec<&str>> {
+    variations.iter().fold(vec![Vec::new()], |combinations, variation| {
+        combinations
+            .iter()
+            .flat_map(|combination| {
+                variation.values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push(value.as_str());
+                    combination
+                })
+            })
+            .collect()
+    })
+}
+
+/// Sets one varied setting on a run's spec.
+fn apply(spec: &mut MosaicSpec, setting: VariedSetting, value: &str) -> AppResult<()> {
+    let invalid = |reason: &str| AppError::InvalidInput(format!("{setting}={value}: {reason}"));
+    let none = value.eq_ignore_ascii_case("none");
+    match setting {
+        VariedSetting::TileSize => {
+            spec.tile_size = value
+                .parse()
+                .ok()
+                .filter(|size| *size > 0)
+                .ok_or_else(|| invalid("expected a tile size above zero"))?;
+        }
+        VariedSetting::Metric => {
+            spec.metric = value.parse().map_err(|error: String| invalid(&error))?;
+        }
+        VariedSetting::Linear => {
+            spec.color_space = match value.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" => ColorSpace::Linear,
+                "false" | "no" | "off" => ColorSpace::Srgb,
+                _ => return Err(invalid("expected true or false")),
+            };
+        }
+        VariedSetting::Dither if none => spec.dither = None,
+        VariedSetting::Dither => {
+            let kernel = value.parse().map_err(|error: String| invalid(&error))?;
+            let serpentine = spec.dither.is_some_and(|dither| dither.serpentine);
+            spec.dither = Some(DitherSpec { kernel, serpentine });
+        }
+        VariedSetting::StructureWeight if none => spec.structure_weight = None,
+        VariedSetting::StructureWeight => {
+            let weight = value
+                .parse::<f32>()
+                .ok()
+                .filter(|weight| *weight >= 0.0)
+                .ok_or_else(|| invalid("expected a weight of zero or more, or none"))?;
+            spec.structure_weight = Some(weight);
+        }
+        VariedSetting::Detail if none => spec.detail = None,
+        VariedSetting::Detail => {
+            let source = value.parse().map_err(|error: String| invalid(&error))?;
+            let base = spec.detail.as_ref();
+            spec.detail = Some(DetailSpec {
+                source,
+                levels: base.map_or(DetailSpec::DEFAULT_LEVELS, |detail| detail.levels),
+                tolerance: base.and_then(|detail| detail.tolerance),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// `path` with the run's settings appended to its file name.
+fn run_path(path: &Path, label: &str) -> PathBuf {
+    let slug: String = label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '=') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}-{slug}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{slug}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Lays thumbnails out in rows of `columns`, each with its label underneath.
+/// Rows are as tall as their tallest thumbnail.
+fn contact_sheet(thumbnails: &[RgbImage], labels: &[&str], columns: usize) -> RgbImage {
+    let columns = columns.clamp(1, thumbnails.len().max(1));
+    let per_line = chars_per_line(THUMBNAIL_WIDTH, LABEL_SCALE);
+    let wrapped: Vec<Vec<String>> = labels.iter().map(|label| wrap(label, per_line)).collect();
+    let label_lines = wrapped.iter().map(Vec::len).max().unwrap_or(1) as u32;
+    let label_height = SHEET_PADDING / 2 + label_lines * line_height(LABEL_SCALE);
+
+    let row_heights: Vec<u32> = thumbnails
+        .chunks(columns)
+        .map(|row| row.iter().map(RgbImage::height).max().unwrap_or(0))
+        .collect();
+    let width = columns as u32 * (THUMBNAIL_WIDTH + SHEET_PADDING) + SHEET_PADDING;
+    let height = row_heights
+        .iter()
+        .map(|row_height| row_height + label_height + SHEET_PADDING)
+        .sum::<u32>()
+        + SHEET_PADDING;
+    let mut sheet = RgbImage::from_pixel(width, height, Rgb(SHEET_BACKGROUND));
+
+    let mut y = SHEET_PADDING;
+    for ((row, row_labels), row_height) in thumbnails
+        .chunks(columns)
+        .zip(wrapped.chunks(columns))
+        .zip(row_heights)
+    {
+        for (column, (thumbnail, lines)) in row.iter().zip(row_labels).enumerate() {
+            let x = SHEET_PADDING + column as u32 * (THUMBNAIL_WIDTH + SHEET_PADDING);
+            imageops::replace(&mut sheet, thumbnail, x as i64, y as i64);
+            let mut line_y = y + row_height + SHEET_PADDING / 2;
+            for line in lines {
+                draw_text(&mut sheet, (x, line_y), line, LABEL_SCALE, LABEL_COLOR);
+                line_y += line_height(LABEL_SCALE);
+            }
+        }
+        y += row_height + label_height + SHEET_PADDING;
+    }
+    sheet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::testing::{mosaic_spec, tile, MemoryCatalog, MemoryImages};
+    use crate::domain::{DetailSource, DitherKernel, MatchMetric};
+
+    fn variation(setting: VariedSetting, values: &[&str]) -> Variation {
+        Variation {
+            setting,
+            values: values.iter().map(|value| value.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn combinations_vary_the_last_setting_fastest() {
+        let variations = [
+            variation(VariedSetting::TileSize, &["8", "16"]),
+            variation(VariedSetting::Metric, &["rgb", "lab", "rgb"]),
+        ];
+        assert_eq!(
+            combinations(&variations),
+            [
+                ["8", "rgb"],
+                ["8", "lab"],
+                ["8", "rgb"],
+                ["16", "rgb"],
+                ["16", "lab"],
+                ["16", "rgb"],
+            ]
+        );
+        assert_eq!(combinations(&variations[..1]), [["8"], ["16"]]);
+    }
+
+    #[test]
+    fn applied_values_replace_the_base_setting() {
+        let mut spec = mosaic_spec("in.png", "sheet.png", 16);
+        spec.dither = Some(DitherSpec {
+            kernel: DitherKernel::FloydSteinberg,
+            serpentine: true,
+        });
+        spec.detail = Some(DetailSpec {
+            source: DetailSource::Auto,
+            levels: 2,
+            tolerance: Some(0.5),
+        });
+
+        apply(&mut spec, VariedSetting::TileSize, "8").unwrap();
+        apply(&mut spec, VariedSetting::Metric, "LAB").unwrap();
+        apply(&mut spec, VariedSetting::Linear, "yes").unwrap();
+        apply(&mut spec, VariedSetting::Dither, "atkinson").unwrap();
+        apply(&mut spec, VariedSetting::StructureWeight, "0.5").unwrap();
+        apply(&mut spec, VariedSetting::Detail, "map.png").unwrap();
+        assert_eq!(spec.tile_size, 8);
+        assert_eq!(spec.metric, MatchMetric::Lab);
+        assert_eq!(spec.color_space, ColorSpace::Linear);
+        // Settings the value does not name are kept from the base.
+        assert_eq!(
+            spec.dither,
+            Some(DitherSpec {
+                kernel: DitherKernel::Atkinson,
+                serpentine: true
+            })
+        );
+        assert_eq!(spec.structure_weight, Some(0.5));
+        let detail = spec.detail.as_ref().unwrap();
+        assert_eq!(detail.source, DetailSource::Map(PathBuf::from("map.png")));
+        assert_eq!((detail.levels, detail.tolerance), (2, Some(0.5)));
+
+        apply(&mut spec, VariedSetting::Linear, "off").unwrap();
+        apply(&mut spec, VariedSetting::Dither, "none").unwrap();
+        apply(&mut spec, VariedSetting::StructureWeight, "None").unwrap();
+        apply(&mut spec, VariedSetting::Detail, "none").unwrap();
+        assert_eq!(spec.color_space, ColorSpace::Srgb);
+        assert_eq!(spec.dither, None);
+        assert_eq!(spec.structure_weight, None);
+        assert!(spec.detail.is_none());
+    }
+
+    #[test]
+    fn invalid_values_name_the_setting() {
+        let mut spec = mosaic_spec("in.png", "sheet.png", 16);
+        for (setting, value) in [
+            (VariedSetting::TileSize, "0"),
+            (VariedSetting::TileSize, "big"),
+            (VariedSetting::Metric, "hsv"),
+            (VariedSetting::Linear, "maybe"),
+            (VariedSetting::Dither, "ordered"),
+            (VariedSetting::StructureWeight, "-1"),
+            (VariedSetting::Detail, " "),
+        ] {
+            let error = apply(&mut spec, setting, value).unwrap_err().to_string();
+            assert!(error.contains(&format!("{setting}={value}")), "{error}");
+        }
+        assert_eq!(spec.tile_size, 16);
+    }
+
+    #[test]
+    fn run_paths_append_a_file_name_safe_label() {
+        let sheet = Path::new("out/sheet.png");
+        assert_eq!(
+            run_path(sheet, "tile_size=8 metric=lab"),
+            Path::new("out/sheet-tile_size=8-metric=lab.png")
+        );
+        assert_eq!(
+            run_path(sheet, "detail=maps/a b.png"),
+            Path::new("out/sheet-detail=maps-a-b.png.png")
+        );
+        assert_eq!(run_path(Path::new("sheet"), "linear=true"), Path::new("sheet-linear=true"));
+    }
+
+    #[test]
+    fn contact_sheet_rows_fit_their_tallest_thumbnail() {
+        let thumbnails = [
+            RgbImage::from_pixel(THUMBNAIL_WIDTH, 100, Rgb([255, 0, 0])),
+            RgbImage::from_pixel(THUMBNAIL_WIDTH, 200, Rgb([0, 255, 0])),
+            RgbImage::from_pixel(THUMBNAIL_WIDTH, 50, Rgb([0, 0, 255])),
+        ];
+        let sheet = contact_sheet(&thumbnails, &["a", "b", "c"], 2);
+
+        let column = THUMBNAIL_WIDTH + SHEET_PADDING;
+        let label_height = SHEET_PADDING / 2 + line_height(LABEL_SCALE);
+        let first_row = 200 + label_height + SHEET_PADDING;
+        let second_row = 50 + label_height + SHEET_PADDING;
+        assert_eq!(sheet.width(), 2 * column + SHEET_PADDING);
+        assert_eq!(sheet.height(), SHEET_PADDING + first_row + second_row);
+
+        let pad = SHEET_PADDING;
+        assert_eq!(sheet.get_pixel(pad, pad).0, [255, 0, 0]);
+        assert_eq!(sheet.get_pixel(pad + column, pad).0, [0, 255, 0]);
+        assert_eq!(sheet.get_pixel(pad, pad + first_row).0, [0, 0, 255]);
+        // Below the shorter thumbnail is background, not the next label.
+        assert_eq!(sheet.get_pixel(pad, pad + 150).0, SHEET_BACKGROUND);
+        // The empty fourth slot stays background.
+        assert_eq!(sheet.get_pixel(pad + column, pad + first_row).0, SHEET_BACKGROUND);
+    }
+
+    #[test]
+    fn long_labels_make_every_label_row_taller() {
+        let thumbnails = [RgbImage::from_pixel(THUMBNAIL_WIDTH, 10, Rgb([0, 0, 0]))];
+        let short = contact_sheet(&thumbnails, &["a"], 1);
+        let long_label = "tile_size=8 metric=lab linear=true";
+        let long = contact_sheet(&thumbnails, &[long_label], 1);
+        assert_eq!(long.width(), short.width());
+        assert_eq!(long.height(), short.height() + line_height(LABEL_SCALE));
+    }
+
+    fn compare_spec(sheet: &str, variations: Vec<Variation>) -> CompareSpec {
+        CompareSpec {
+            base: mosaic_spec("in.png", sheet, 4),
+            variations,
+        }
+    }
+
+    #[test]
+    fn every_combination_is_rendered_next_to_the_sheet() {
+        let catalog = MemoryCatalog::new(vec![tile("gray", "gray.png", [128, 128, 128])]);
+        let images = MemoryImages::default()
+            .with("in.png", RgbImage::from_pixel(8, 8, Rgb([120, 120, 120])))
+            .with("gray.png", RgbImage::from_pixel(4, 4, Rgb([128, 128, 128])));
+        let spec = compare_spec(
+            "out/sheet.png",
+            vec![
+                variation(VariedSetting::TileSize, &["2", "4"]),
+                variation(VariedSetting::Linear, &["false", "true"]),
+            ],
+        );
+
+        let result = compare_settings(&catalog, &images, &spec).unwrap();
+        let labels: Vec<&str> = result.runs.iter().map(|run| run.label.as_str()).collect();
+        assert_eq!(
+            labels,
+            [
+                "tile_size=2 linear=false",
+                "tile_size=2 linear=true",
+                "tile_size=4 linear=false",
+                "tile_size=4 linear=true",
+            ]
+        );
+        let grids: Vec<u32> = result.runs.iter().map(|run| run.result.grid_width).collect();
+        assert_eq!(grids, [4, 4, 2, 2]);
+        for run in &result.runs {
+            assert!(images.get(run.result.output.to_str().unwrap()).is_some());
+        }
+        assert!(images.get("out/sheet-tile_size=2-linear=false.png").is_some());
+
+        // Two columns, one per value of the last setting.
+        let sheet = images.get("out/sheet.png").unwrap();
+        let column = THUMBNAIL_WIDTH + SHEET_PADDING;
+        assert_eq!(sheet.width(), 2 * column + SHEET_PADDING);
+    }
+
+    #[test]
+    fn sheets_and_values_are_checked_before_any_run() {
+        let catalog = MemoryCatalog::new(vec![tile("gray", "gray.png", [128, 128, 128])]);
+        let images = MemoryImages::default()
+            .with("in.png", RgbImage::from_pixel(8, 8, Rgb([120, 120, 120])))
+            .with("gray.png", RgbImage::from_pixel(4, 4, Rgb([128, 128, 128])));
+        let sizes = || vec![variation(VariedSetting::TileSize, &["4", "0"])];
+
+        let pyramid = compare_spec("sheet.dzi", vec![variation(VariedSetting::TileSize, &["4"])]);
+        assert!(compare_settings(&catalog, &images, &pyramid).is_err());
+        assert!(compare_settings(&catalog, &images, &compare_spec("sheet.png", vec![])).is_err());
+        assert!(compare_settings(&catalog, &images, &compare_spec("sheet.png", sizes())).is_err());
+        assert!(images.get("sheet-tile_size=4.png").is_none());
+    }
+}
@@ -0,0 +1,52 @@
+use image::{imageops, DynamicImage, GrayImage, Luma};
+
+/// Luma standard deviation at which a cell counts as fully important.
+const FULL_CONTRAST: f64 = 48.0;
+
+/// Importance of each `cell_size` square of the input, 0 (plain) to 255 (fine
+/// detail), one pixel per square. Taken from `map` when given, otherwise from
+/// the spread of luma inside each square.
+pub(crate) fn importance_map(
+    input: &DynamicImage,
+    map: Option<&DynamicImage>,
+    cell_size: u32,
+) -> GrayImage {
+    let width = (input.width() / cell_size).max(1);
+    let height = (input.height() / cell_size).max(1);
+    if let Some(map) = map {
+        return imageops::resize(&map.to_luma8(), width, height, imageops::FilterType::Triangle);
+    }
+
+    let luma = input.to_luma8();
+    GrayImage::from_fn(width, height, |cell_x, cell_y| {
+        let mut sum = 0u64;
+        let mut sq_sum = 0u64;
+        let mut count = 0u64;
+        for y in cell_y * cell_size..((cell_y + 1) * cell_size).min(luma.height()) {
+            for x in cell_x * cell_size..((cell_x + 1) * cell_size).min(luma.width()) {
+                let value = luma.get_pixel(x, y)[0] as u64;
+                sum += value;
+                sq_sum += value * value;
+                count += 1;
+            }
+        }
+        if count == 0 {
+            return Luma([0]);
+        }
+        let mean = sum as f64 / count as f64;
+        let deviation = (sq_sum as f64 / count as f64 - mean * mean).max(0.0).sqrt();
+        Luma([((deviation / FULL_CONTRAST).min(1.0) * 255.0).round() as u8])
+    })
+}
+
+/// Importance of a square of `cells` x `cells` map pixels at (`x`, `y`), 0.0
+/// to 1.0. The peak is used so a single detailed spot is enough to refine it.
+pub(crate) fn peak_importance(map: &GrayImage, x: u32, y: u32, cells: u32) -> f32 {
+    let mut peak = 0u8;
+    for map_y in y..(y + cells).min(map.height()) {
+        for map_x in x..(x + cells).min(map.width()) {
+            peak = peak.max(map.get_pixel(map_x, map_y)[0]);
+        }
+    }
+    peak as f32 / 255.0
+}
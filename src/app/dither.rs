@@ -0,0 +1,131 @@
+use crate::domain::{DitherKernel, DitherSpec};
+
+/// Share of a cell's error each neighbour gets, as (columns ahead, rows down, weight).
+const FLOYD_STEINBERG: [(i64, i64, f32); 4] = [
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+/// Spreads only three quarters of the error, which keeps contrast at the cost
+/// of some accuracy in flat areas.
+const ATKINSON: [(i64, i64, f32); 6] = [
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+/// Color error carried from matched cells to the cells not yet visited, for
+/// the whole grid so it crosses band boundaries.
+pub(crate) struct Diffusion {
+    kernel: &'static [(i64, i64, f32)],
+    serpentine: bool,
+    width: u32,
+    height: u32,
+    errors: Vec<[f32; 3]>,
+}
+
+impl Diffusion {
+    pub(crate) fn new(spec: DitherSpec, (width, height): (u32, u32)) -> Self {
+        let kernel: &'static [(i64, i64, f32)] = match spec.kernel {
+            DitherKernel::FloydSteinberg => &FLOYD_STEINBERG,
+            DitherKernel::Atkinson => &ATKINSON,
+        };
+        Diffusion {
+            kernel,
+            serpentine: spec.serpentine,
+            width,
+            height,
+            errors: vec![[0.0; 3]; width as usize * height as usize],
+        }
+    }
+
+    /// The column visited at `step` of `row`; serpentine order runs odd rows
+    /// right to left.
+    pub(crate) fn column(&self, row: u32, step: u32) -> u32 {
+        if self.reversed(row) {
+            self.width - 1 - step
+        } else {
+            step
+        }
+    }
+
+    /// Error that has reached a cell, to add to its color before matching.
+    pub(crate) fn error(&self, row: u32, column: u32) -> [f32; 3] {
+        self.errors[(row * self.width + column) as usize]
+    }
+
+    /// Hands a cell's remaining error on to its unvisited neighbours.
+    pub(crate) fn spread(&mut self, row: u32, column: u32, error: [f32; 3]) {
+        let direction = if self.reversed(row) { -1 } else { 1 };
+        for &(dx, dy, weight) in self.kernel {
+            let x = column as i64 + dx * direction;
+            let y = row as i64 + dy;
+            if x < 0 || x >= self.width as i64 || y >= self.height as i64 {
+                continue;
+            }
+            let cell = &mut self.errors[(y * self.width as i64 + x) as usize];
+            for (value, error) in cell.iter_mut().zip(error) {
+                *value += error * weight;
+            }
+        }
+    }
+
+    fn reversed(&self, row: u32) -> bool {
+        self.serpentine && row % 2 == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diffusion(kernel: DitherKernel, serpentine: bool, grid: (u32, u32)) -> Diffusion {
+        Diffusion::new(DitherSpec { kernel, serpentine }, grid)
+    }
+
+    #[test]
+    fn floyd_steinberg_spreads_all_error_and_atkinson_three_quarters() {
+        let total = |kernel: &[(i64, i64, f32)]| kernel.iter().map(|(_, _, w)| w).sum::<f32>();
+        assert_eq!(total(&FLOYD_STEINBERG), 1.0);
+        assert_eq!(total(&ATKINSON), 0.75);
+    }
+
+    #[test]
+    fn error_goes_to_unvisited_neighbours() {
+        let mut dither = diffusion(DitherKernel::FloydSteinberg, false, (3, 2));
+        dither.spread(0, 1, [16.0, -32.0, 0.0]);
+        assert_eq!(dither.error(0, 2), [7.0, -14.0, 0.0]);
+        assert_eq!(dither.error(1, 0), [3.0, -6.0, 0.0]);
+        assert_eq!(dither.error(1, 1), [5.0, -10.0, 0.0]);
+        assert_eq!(dither.error(1, 2), [1.0, -2.0, 0.0]);
+        assert_eq!(dither.error(0, 0), [0.0; 3]);
+    }
+
+    #[test]
+    fn error_past_the_grid_edges_is_dropped() {
+        let mut dither = diffusion(DitherKernel::Atkinson, false, (2, 2));
+        dither.spread(1, 1, [8.0; 3]);
+        assert!((0..2).all(|row| (0..2).all(|column| dither.error(row, column) == [0.0; 3])));
+        dither.spread(0, 1, [8.0; 3]);
+        assert_eq!(dither.error(1, 0), [1.0; 3]);
+        assert_eq!(dither.error(1, 1), [1.0; 3]);
+    }
+
+    #[test]
+    fn serpentine_rows_alternate_direction() {
+        let mut dither = diffusion(DitherKernel::FloydSteinberg, true, (3, 3));
+        assert_eq!((0..3).map(|step| dither.column(0, step)).collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!((0..3).map(|step| dither.column(1, step)).collect::<Vec<_>>(), [2, 1, 0]);
+
+        // On a reversed row the error runs to the left and mirrors below.
+        dither.spread(1, 1, [16.0; 3]);
+        assert_eq!(dither.error(1, 0), [7.0; 3]);
+        assert_eq!(dither.error(1, 2), [0.0; 3]);
+        assert_eq!(dither.error(2, 2), [3.0; 3]);
+        assert_eq!(dither.error(2, 0), [1.0; 3]);
+    }
+}
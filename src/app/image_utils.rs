@@ -1,20 +1,231 @@
-use image::DynamicImage;
+use crate::domain::{ColorSpace, HashKind};
+use crate::error::{AppError, AppResult};
+use image::{imageops, DynamicImage, Rgb, Rgb32FImage, RgbImage};
+use std::collections::HashMap;
+use std::sync::LazyLock;
 
-pub fn average_color(image: &DynamicImage) -> [u8; 3] {
-    let rgb = image.to_rgb8();
-    let mut sums = [0u64; 3];
-    for pixel in rgb.pixels() {
-        sums[0] += pixel[0] as u64;
-        sums[1] += pixel[1] as u64;
-        sums[2] += pixel[2] as u64;
+/// sRGB byte to linear-light intensity, 0.0 to 1.0.
+static LINEAR_LUT: LazyLock<[f32; 256]> = LazyLock::new(|| {
+    std::array::from_fn(|value| {
+        let encoded = value as f32 / 255.0;
+        if encoded <= 0.04045 {
+            encoded / 12.92
+        } else {
+            ((encoded + 0.055) / 1.055).powf(2.4)
+        }
+    })
+});
+
+pub fn srgb_to_linear(value: u8) -> f32 {
+    LINEAR_LUT[value as usize]
+}
+
+pub fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let encoded = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/// Averages the visible color: each pixel counts in proportion to its alpha,
+/// so transparent areas do not drag the result toward black.
+pub fn average_color(image: &DynamicImage, space: ColorSpace) -> [u8; 3] {
+    let rgba = image.to_rgba8();
+    // A fully transparent image has no visible color; fall back to a plain average.
+    let weighted = rgba.pixels().any(|pixel| pixel[3] > 0);
+
+    let mut sums = [0f64; 3];
+    let mut total = 0f64;
+    for pixel in rgba.pixels() {
+        let [r, g, b, a] = pixel.0;
+        let weight = if weighted { a as f64 / 255.0 } else { 1.0 };
+        for (sum, value) in sums.iter_mut().zip([r, g, b]) {
+            let value = match space {
+                ColorSpace::Srgb => value as f64,
+                ColorSpace::Linear => srgb_to_linear(value) as f64,
+            };
+            *sum += value * weight;
+        }
+        total += weight;
+    }
+
+    if total == 0.0 {
+        return [0; 3];
+    }
+    sums.map(|sum| match space {
+        ColorSpace::Srgb => (sum / total) as u8,
+        ColorSpace::Linear => linear_to_srgb((sum / total) as f32),
+    })
+}
+
+/// Composites an image with transparency over an opaque `matte` color.
+pub fn flatten(image: DynamicImage, matte: [u8; 3], space: ColorSpace) -> DynamicImage {
+    if !image.color().has_alpha() {
+        return image;
+    }
+
+    let rgba = image.to_rgba8();
+    let flattened = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let alpha = a as f32 / 255.0;
+        let mut color = [0u8; 3];
+        for ((out, value), background) in color.iter_mut().zip([r, g, b]).zip(matte) {
+            *out = match space {
+                ColorSpace::Srgb => {
+                    (value as f32 * alpha + background as f32 * (1.0 - alpha)).round() as u8
+                }
+                ColorSpace::Linear => linear_to_srgb(
+                    srgb_to_linear(value) * alpha + srgb_to_linear(background) * (1.0 - alpha),
+                ),
+            };
+        }
+        Rgb(color)
+    });
+    DynamicImage::ImageRgb8(flattened)
+}
+
+/// Resizes to exactly `width` x `height`, filtering in the requested color space.
+pub fn resize_exact(image: &DynamicImage, width: u32, height: u32, space: ColorSpace) -> RgbImage {
+    match space {
+        ColorSpace::Srgb => image
+            .resize_exact(width, height, imageops::FilterType::Triangle)
+            .to_rgb8(),
+        ColorSpace::Linear => {
+            let rgb = image.to_rgb8();
+            let linear = Rgb32FImage::from_fn(rgb.width(), rgb.height(), |x, y| {
+                Rgb(rgb.get_pixel(x, y).0.map(srgb_to_linear))
+            });
+            let resized = imageops::resize(&linear, width, height, imageops::FilterType::Triangle);
+            RgbImage::from_fn(width, height, |x, y| {
+                Rgb(resized.get_pixel(x, y).0.map(linear_to_srgb))
+            })
+        }
     }
+}
 
-    let count = (rgb.width() as u64).saturating_mul(rgb.height() as u64).max(1);
-    [
-        (sums[0] / count) as u8,
-        (sums[1] / count) as u8,
-        (sums[2] / count) as u8,
-    ]
+pub struct ImageStats {
+    pub brightness: f32,
+    pub contrast: f32,
+    pub saturation: f32,
+    pub color_variance: f32,
+    pub dominant_colors: Vec<[u8; 3]>,
+    pub sharpness: f32,
+    pub underexposed: f32,
+    pub overexposed: f32,
+}
+
+const STATS_SAMPLE_SIZE: u32 = 128;
+const SHARPNESS_SAMPLE_SIZE: u32 = 512;
+const DOMINANT_COLORS: usize = 3;
+const UNDEREXPOSED_LUMA: f32 = 0.04;
+const OVEREXPOSED_LUMA: f32 = 0.96;
+
+pub fn image_stats(image: &DynamicImage) -> ImageStats {
+    let sample = if image.width() > STATS_SAMPLE_SIZE || image.height() > STATS_SAMPLE_SIZE {
+        image.thumbnail(STATS_SAMPLE_SIZE, STATS_SAMPLE_SIZE).to_rgb8()
+    } else {
+        image.to_rgb8()
+    };
+    let count = (sample.width() as usize * sample.height() as usize).max(1) as f32;
+
+    let mut luma_sum = 0.0;
+    let mut luma_sq_sum = 0.0;
+    let mut dark = 0usize;
+    let mut bright = 0usize;
+    let mut saturation_sum = 0.0;
+    let mut channel_sums = [0.0f32; 3];
+    let mut channel_sq_sums = [0.0f32; 3];
+    let mut buckets: HashMap<u16, (usize, [u32; 3])> = HashMap::new();
+
+    for pixel in sample.pixels() {
+        let [r, g, b] = pixel.0;
+        let luma = (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32) / 255.0;
+        luma_sum += luma;
+        luma_sq_sum += luma * luma;
+        if luma < UNDEREXPOSED_LUMA {
+            dark += 1;
+        } else if luma > OVEREXPOSED_LUMA {
+            bright += 1;
+        }
+
+        let max = r.max(g).max(b) as f32;
+        let min = r.min(g).min(b) as f32;
+        if max > 0.0 {
+            saturation_sum += (max - min) / max;
+        }
+
+        for (channel, value) in pixel.0.iter().enumerate() {
+            channel_sums[channel] += *value as f32;
+            channel_sq_sums[channel] += (*value as f32).powi(2);
+        }
+
+        let key = ((r as u16 >> 5) << 6) | ((g as u16 >> 5) << 3) | (b as u16 >> 5);
+        let bucket = buckets.entry(key).or_default();
+        bucket.0 += 1;
+        for (sum, value) in bucket.1.iter_mut().zip(pixel.0) {
+            *sum += value as u32;
+        }
+    }
+
+    let brightness = luma_sum / count;
+    let contrast = (luma_sq_sum / count - brightness * brightness).max(0.0).sqrt();
+    let color_variance = (0..3)
+        .map(|channel| {
+            let mean = channel_sums[channel] / count;
+            (channel_sq_sums[channel] / count - mean * mean).max(0.0)
+        })
+        .sum::<f32>()
+        / 3.0;
+
+    let mut buckets: Vec<(usize, [u32; 3])> = buckets.into_values().collect();
+    buckets.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    let dominant_colors = buckets
+        .iter()
+        .take(DOMINANT_COLORS)
+        .map(|(count, sums)| sums.map(|sum| (sum / *count as u32) as u8))
+        .collect();
+
+    ImageStats {
+        brightness,
+        contrast,
+        saturation: saturation_sum / count,
+        color_variance,
+        dominant_colors,
+        sharpness: laplacian_variance(image),
+        underexposed: dark as f32 / count,
+        overexposed: bright as f32 / count,
+    }
+}
+
+/// Variance of the 4-neighbour Laplacian over luma (0-255 scale).
+fn laplacian_variance(image: &DynamicImage) -> f32 {
+    let luma = if image.width() > SHARPNESS_SAMPLE_SIZE || image.height() > SHARPNESS_SAMPLE_SIZE {
+        image.thumbnail(SHARPNESS_SAMPLE_SIZE, SHARPNESS_SAMPLE_SIZE).to_luma8()
+    } else {
+        image.to_luma8()
+    };
+    let (width, height) = luma.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let at = |x: u32, y: u32| luma.get_pixel(x, y)[0] as f32;
+    let mut sum = 0.0;
+    let mut sq_sum = 0.0;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let value = at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * at(x, y);
+            sum += value;
+            sq_sum += value * value;
+        }
+    }
+
+    let count = ((width - 2) * (height - 2)) as f32;
+    let mean = sum / count;
+    (sq_sum / count - mean * mean).max(0.0)
 }
 
 pub fn color_distance(a: [u8; 3], b: [u8; 3]) -> u32 {
@@ -23,3 +234,171 @@ pub fn color_distance(a: [u8; 3], b: [u8; 3]) -> u32 {
     let db = a[2] as i32 - b[2] as i32;
     (dr * dr + dg * dg + db * db) as u32
 }
+
+/// CIE L*a*b* of an sRGB color, relative to the D65 white point.
+pub fn srgb_to_lab(color: [u8; 3]) -> [f32; 3] {
+    let [r, g, b] = color.map(srgb_to_linear);
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.950_47;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.088_83;
+    let f = |t: f32| {
+        if t > 0.008_856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// CIE76 color difference: the distance between two colors in L*a*b*.
+pub fn delta_e(a: [u8; 3], b: [u8; 3]) -> f32 {
+    let (a, b) = (srgb_to_lab(a), srgb_to_lab(b));
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt()
+}
+
+/// Parses `#rrggbb` or `rrggbb`.
+pub fn parse_hex_color(value: &str) -> AppResult<[u8; 3]> {
+    let hex = value.trim().trim_start_matches('#');
+    let invalid = || AppError::InvalidInput(format!("invalid color: {value} (expected #rrggbb)"));
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| invalid());
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Formats a color as `#rrggbb`.
+pub fn hex_color(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+pub fn perceptual_hash(image: &DynamicImage, kind: HashKind) -> u64 {
+    match kind {
+        HashKind::Average => average_hash(image),
+        HashKind::Difference => difference_hash(image),
+        HashKind::Perceptual => dct_hash(image),
+    }
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+fn grayscale_samples(image: &DynamicImage, width: u32, height: u32) -> Vec<f32> {
+    image
+        .resize_exact(width, height, imageops::FilterType::Triangle)
+        .to_luma8()
+        .pixels()
+        .map(|pixel| pixel[0] as f32)
+        .collect()
+}
+
+fn bits_above(values: &[f32], threshold: f32) -> u64 {
+    values
+        .iter()
+        .enumerate()
+        .filter(|(_, value)| **value > threshold)
+        .fold(0u64, |hash, (index, _)| hash | (1 << index))
+}
+
+fn average_hash(image: &DynamicImage) -> u64 {
+    let samples = grayscale_samples(image, 8, 8);
+    let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+    bits_above(&samples, mean)
+}
+
+fn difference_hash(image: &DynamicImage) -> u64 {
+    let samples = grayscale_samples(image, 9, 8);
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            if samples[y * 9 + x] < samples[y * 9 + x + 1] {
+                hash |= 1 << (y * 8 + x);
+            }
+        }
+    }
+    hash
+}
+
+fn dct_hash(image: &DynamicImage) -> u64 {
+    const SIZE: usize = 32;
+    let samples = grayscale_samples(image, SIZE as u32, SIZE as u32);
+    let basis: Vec<f32> = (0..8)
+        .flat_map(|u| {
+            (0..SIZE).map(move |x| {
+                (std::f32::consts::PI * (2 * x + 1) as f32 * u as f32 / (2 * SIZE) as f32).cos()
+            })
+        })
+        .collect();
+
+    let mut coefficients = [0f32; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            let mut sum = 0.0;
+            for y in 0..SIZE {
+                let row = basis[v * SIZE + y];
+                for x in 0..SIZE {
+                    sum += samples[y * SIZE + x] * basis[u * SIZE + x] * row;
+                }
+            }
+            coefficients[v * 8 + u] = sum;
+        }
+    }
+
+    let mut sorted: Vec<f32> = coefficients[1..].to_vec();
+    sorted.sort_by(f32::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    bits_above(&coefficients, median) & !1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn checker(dark: u8, light: u8) -> DynamicImage {
+        let value = |x: u32, y: u32| if (x + y).is_multiple_of(2) { dark } else { light };
+        DynamicImage::ImageRgb8(RgbImage::from_fn(4, 4, |x, y| Rgb([value(x, y); 3])))
+    }
+
+    #[test]
+    fn linear_conversion_round_trips() {
+        assert!((0..=255).all(|value| linear_to_srgb(srgb_to_linear(value)) == value));
+        assert_eq!(srgb_to_linear(0), 0.0);
+        assert_eq!(srgb_to_linear(255), 1.0);
+    }
+
+    #[test]
+    fn linear_averages_are_lighter() {
+        let image = checker(0, 255);
+        assert_eq!(average_color(&image, ColorSpace::Srgb), [127; 3]);
+        // Half the light of white, encoded back to sRGB.
+        assert_eq!(average_color(&image, ColorSpace::Linear), [188; 3]);
+
+        let resized = resize_exact(&image, 1, 1, ColorSpace::Linear);
+        assert!(resized.get_pixel(0, 0).0.iter().all(|value| value.abs_diff(188) <= 1));
+    }
+    #[test]
+    fn averages_weight_pixels_by_alpha() {
+        let pixels = [Rgba([200, 0, 0, 255]), Rgba([0, 200, 0, 0])];
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(2, 1, |x, _| pixels[x as usize]));
+        assert_eq!(average_color(&image, ColorSpace::Srgb), [200, 0, 0]);
+
+        let hidden = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 2, Rgba([10, 20, 30, 0])));
+        assert_eq!(average_color(&hidden, ColorSpace::Srgb), [10, 20, 30]);
+    }
+
+    #[test]
+    fn flatten_composites_over_the_matte() {
+        let alphas = [255, 0, 128];
+        let image = RgbaImage::from_fn(3, 1, |x, _| Rgba([255, 255, 255, alphas[x as usize]]));
+        let flat = flatten(DynamicImage::ImageRgba8(image), [0, 0, 255], ColorSpace::Srgb);
+        let flat = flat.as_rgb8().unwrap();
+        assert_eq!(flat.get_pixel(0, 0).0, [255, 255, 255]);
+        assert_eq!(flat.get_pixel(1, 0).0, [0, 0, 255]);
+        assert_eq!(flat.get_pixel(2, 0).0, [128, 128, 255]);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::app::image_utils::color_distance;
+use image::DynamicImage;
+
+/// Squared RGB distance within which a mask pixel counts towards a region
+/// color, so anti-aliased or lossy masks still classify cleanly.
+const MASK_TOLERANCE: u32 = 64 * 64;
+
+/// Assigns each grid cell to the region color most of its mask pixels are
+/// closest to, in row-major order. Cells mostly covered by other colors get
+/// `None`. The mask is stretched over the whole input.
+pub(crate) fn cell_regions(
+    mask: &DynamicImage,
+    colors: &[[u8; 3]],
+    (input_width, input_height): (u32, u32),
+    tile_size: u32,
+    (grid_width, grid_height): (u32, u32),
+) -> Vec<Option<usize>> {
+    let mask = mask.to_rgb8();
+    let scale_x = |x: u32| (x as u64 * mask.width() as u64 / input_width as u64) as u32;
+    let scale_y = |y: u32| (y as u64 * mask.height() as u64 / input_height as u64) as u32;
+
+    let mut cells = Vec::with_capacity(grid_width as usize * grid_height as usize);
+    let mut votes = vec![0usize; colors.len() + 1];
+    for row in 0..grid_height {
+        let top = scale_y(row * tile_size);
+        let bottom = scale_y((row + 1) * tile_size).max(top + 1).min(mask.height());
+        for column in 0..grid_width {
+            let left = scale_x(column * tile_size);
+            let right = scale_x((column + 1) * tile_size).max(left + 1).min(mask.width());
+
+            votes.fill(0);
+            for y in top..bottom {
+                for x in left..right {
+                    let pixel = mask.get_pixel(x, y).0;
+                    let region = colors
+                        .iter()
+                        .map(|color| color_distance(*color, pixel))
+                        .enumerate()
+                        .filter(|(_, distance)| *distance <= MASK_TOLERANCE)
+                        .min_by_key(|(_, distance)| *distance)
+                        .map_or(colors.len(), |(index, _)| index);
+                    votes[region] += 1;
+                }
+            }
+
+            // Ties go to the earlier region; the last slot is "no region".
+            let winner = votes
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(&a.0)))
+                .map_or(colors.len(), |(index, _)| index);
+            cells.push((winner < colors.len()).then_some(winner));
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::mosaic::generate_mosaic;
+    use crate::app::testing::{mosaic_spec, tile, MemoryCatalog, MemoryImages};
+    use crate::domain::{MaskRegion, RegionMask, TilesSource};
+    use image::{Rgb, RgbImage};
+    use std::path::PathBuf;
+
+    const RED: [u8; 3] = [255, 0, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+    const GREEN: [u8; 3] = [0, 255, 0];
+
+    /// A mask drawn from rows of pixels.
+    fn mask(rows: &[&[[u8; 3]]]) -> DynamicImage {
+        let image = RgbImage::from_fn(rows[0].len() as u32, rows.len() as u32, |x, y| {
+            Rgb(rows[y as usize][x as usize])
+        });
+        DynamicImage::ImageRgb8(image)
+    }
+
+    #[test]
+    fn cells_go_to_the_region_most_of_their_pixels_vote_for() {
+        let mask = mask(&[
+            &[RED, RED, RED, BLUE, BLUE, BLUE, RED, GREEN],
+            &[RED, BLUE, RED, BLUE, BLUE, GREEN, GREEN, GREEN],
+        ]);
+        let regions = cell_regions(&mask, &[RED, BLUE], (8, 2), 2, (4, 1));
+        // Three to one; a tie goes to the earlier region; unmapped pixels count
+        // as votes but win no region; most pixels unmapped means no region.
+        assert_eq!(regions, [Some(0), Some(0), Some(1), None]);
+    }
+
+    #[test]
+    fn pixels_near_a_region_color_count_towards_it() {
+        let near_red = [220, 20, 20];
+        let far_red = [160, 0, 0];
+        let mask = mask(&[&[near_red, far_red]]);
+        assert_eq!(cell_regions(&mask, &[RED, BLUE], (2, 1), 1, (2, 1)), [Some(0), None]);
+
+        // Within tolerance of both, the closer color wins, not the earlier one.
+        let between = [20, 0, 200];
+        let mask = self::mask(&[&[between]]);
+        assert_eq!(cell_regions(&mask, &[BLUE, [0, 0, 160]], (1, 1), 1, (1, 1)), [Some(1)]);
+    }
+
+    #[test]
+    fn the_mask_is_stretched_over_the_input() {
+        let mask = mask(&[&[RED, BLUE]]);
+        let regions = cell_regions(&mask, &[RED, BLUE], (8, 4), 2, (4, 2));
+        let expected = [Some(0), Some(0), Some(1), Some(1)];
+        assert_eq!(regions[..4], expected);
+        assert_eq!(regions[4..], expected);
+    }
+
+    #[test]
+    fn cells_outside_every_region_draw_from_the_default_tiles() {
+        let mut warm = tile("warm", "warm.png", [200, 0, 0]);
+        warm.tags = vec!["warm".to_string()];
+        let catalog = MemoryCatalog::new(vec![warm, tile("gray", "gray.png", [128, 128, 128])]);
+        let images = MemoryImages::default()
+            .with("in.png", RgbImage::from_pixel(4, 2, Rgb([128, 128, 128])))
+            .with("mask.png", RgbImage::from_fn(2, 1, |x, _| Rgb([RED, GREEN][x as usize])))
+            .with("warm.png", RgbImage::from_pixel(2, 2, Rgb([200, 0, 0])))
+            .with("gray.png", RgbImage::from_pixel(2, 2, Rgb([128, 128, 128])));
+        let mut spec = mosaic_spec("in.png", "out.png", 2);
+        spec.mask = Some(RegionMask {
+            path: PathBuf::from("mask.png"),
+            regions: vec![MaskRegion {
+                color: RED,
+                source: TilesSource::Catalog,
+                tags: vec!["warm".to_string()],
+            }],
+        });
+        spec.manifest = Some(PathBuf::from("out.json"));
+
+        let result = generate_mosaic(&catalog, &images, &spec).unwrap();
+        let placements = result.manifest.unwrap().placements;
+        let tiles: Vec<(u32, &str)> = placements
+            .iter()
+            .map(|placement| (placement.column, placement.tile_id.as_str()))
+            .collect();
+        // The red region only has the warm tile, even though gray is closer.
+        assert_eq!(tiles, [(0, "warm"), (1, "gray")]);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::app::image_utils::delta_e;
+use crate::domain::MosaicQuality;
+use image::{imageops, Rgb, RgbImage};
+
+/// Pixels per cell edge the input and mosaic are compared at, so tiles are
+/// judged by how they read from a distance rather than by their content.
+const METRIC_CELL_PIXELS: u32 = 4;
+/// Edge and stride of the windows SSIM is averaged over.
+const SSIM_WINDOW: u32 = 8;
+const SSIM_STRIDE: u32 = 4;
+/// Cell ΔE drawn at full heat; a fixed scale keeps heatmaps comparable.
+const HEATMAP_MAX_DELTA_E: f32 = 50.0;
+
+/// The input and the mosaic, shrunk side by side as bands are rendered.
+pub(crate) struct Comparison {
+    tile_size: u32,
+    scale: u32,
+    source: RgbImage,
+    mosaic: RgbImage,
+}
+
+impl Comparison {
+    pub(crate) fn new((grid_width, grid_height): (u32, u32), tile_size: u32) -> Self {
+        let scale = METRIC_CELL_PIXELS.min(tile_size);
+        Comparison {
+            tile_size,
+            scale,
+            source: RgbImage::new(grid_width * scale, grid_height * scale),
+            mosaic: RgbImage::new(grid_width * scale, grid_height * scale),
+        }
+    }
+
+    /// Bytes held for the whole grid.
+    pub(crate) fn bytes((grid_width, grid_height): (u32, u32), tile_size: u32) -> u64 {
+        let scale = METRIC_CELL_PIXELS.min(tile_size) as u64;
+        grid_width as u64 * grid_height as u64 * scale * scale * 3 * 2
+    }
+
+    /// Adds a band of whole grid rows starting at `first_row`, as the input
+    /// (composited over the matte) and the mosaic show it.
+    pub(crate) fn add_band(&mut self, first_row: u32, source: &RgbImage, mosaic: &RgbImage) {
+        let top = (first_row * self.scale) as i64;
+        let source = shrink_cells(source, self.tile_size, self.scale);
+        let mosaic = shrink_cells(mosaic, self.tile_size, self.scale);
+        imageops::replace(&mut self.source, &source, 0, top);
+        imageops::replace(&mut self.mosaic, &mosaic, 0, top);
+    }
+
+    pub(crate) fn quality(&self) -> MosaicQuality {
+        measure(&self.source, &self.mosaic)
+    }
+
+    pub(crate) fn heatmap(&self) -> RgbImage {
+        heatmap(&self.source, &self.mosaic, self.scale)
+    }
+}
+
+/// Shrinks an image of `tile_size` cells to `scale` pixels per cell edge by
+/// averaging, so bands can be shrunk one at a time with the same result.
+fn shrink_cells(image: &RgbImage, tile_size: u32, scale: u32) -> RgbImage {
+    let edges = |index: u32| {
+        let (cell, part) = (index / scale, index % scale);
+        let start = cell * tile_size + part * tile_size / scale;
+        let end = cell * tile_size + (part + 1) * tile_size / scale;
+        start..end.max(start + 1)
+    };
+    let width = image.width() / tile_size * scale;
+    let height = image.height() / tile_size * scale;
+    RgbImage::from_fn(width, height, |x, y| {
+        let mut sum = [0u64; 3];
+        let mut count = 0u64;
+        for source_y in edges(y) {
+            for source_x in edges(x) {
+                for (total, value) in sum.iter_mut().zip(image.get_pixel(source_x, source_y).0) {
+                    *total += value as u64;
+                }
+                count += 1;
+            }
+        }
+        Rgb(sum.map(|total| ((total + count / 2) / count) as u8))
+    })
+}
+
+/// Compares a shrunk input with the mosaic shrunk the same way.
+fn measure(source: &RgbImage, mosaic: &RgbImage) -> MosaicQuality {
+    let mut squared = 0.0f64;
+    let mut delta = 0.0f64;
+    for (a, b) in source.pixels().zip(mosaic.pixels()) {
+        for (a, b) in a.0.iter().zip(b.0) {
+            let difference = *a as f64 - b as f64;
+            squared += difference * difference;
+        }
+        delta += delta_e(a.0, b.0) as f64;
+    }
+    let pixels = (source.width() as u64 * source.height() as u64).max(1) as f64;
+    let mse = squared / (pixels * 3.0);
+    let psnr = if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    };
+    MosaicQuality {
+        psnr,
+        ssim: ssim(&luma(source), &luma(mosaic), source.width(), source.height()),
+        mean_delta_e: delta / pixels,
+    }
+}
+
+/// Mean ΔE of each cell drawn from black through red and yellow to white.
+fn heatmap(source: &RgbImage, mosaic: &RgbImage, scale: u32) -> RgbImage {
+    let (columns, rows) = (source.width() / scale, source.height() / scale);
+    let mut heat = RgbImage::new(source.width(), source.height());
+    for row in 0..rows {
+        for column in 0..columns {
+            let mut total = 0.0;
+            for y in row * scale..(row + 1) * scale {
+                for x in column * scale..(column + 1) * scale {
+                    total += delta_e(source.get_pixel(x, y).0, mosaic.get_pixel(x, y).0);
+                }
+            }
+            let level = (total / (scale * scale) as f32 / HEATMAP_MAX_DELTA_E).min(1.0) * 3.0;
+            let channel = |start: f32| ((level - start).clamp(0.0, 1.0) * 255.0).round() as u8;
+            let color = Rgb([channel(0.0), channel(1.0), channel(2.0)]);
+            for y in row * scale..(row + 1) * scale {
+                for x in column * scale..(column + 1) * scale {
+                    heat.put_pixel(x, y, color);
+                }
+            }
+        }
+    }
+    heat
+}
+
+fn luma(image: &RgbImage) -> Vec<f64> {
+    image
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b] = pixel.0.map(f64::from);
+            0.299 * r + 0.587 * g + 0.114 * b
+        })
+        .collect()
+}
+
+/// Mean structural similarity of two luma planes over overlapping windows,
+/// or over the whole plane when it is smaller than a window.
+fn ssim(a: &[f64], b: &[f64], width: u32, height: u32) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let window_width = SSIM_WINDOW.min(width);
+    let window_height = SSIM_WINDOW.min(height);
+    let mut total = 0.0;
+    let mut windows = 0u32;
+    for top in (0..=height - window_height).step_by(SSIM_STRIDE as usize) {
+        for left in (0..=width - window_width).step_by(SSIM_STRIDE as usize) {
+            let indices = (top..top + window_height).flat_map(|y| {
+                (left..left + window_width).map(move |x| (y * width + x) as usize)
+            });
+            let count = (window_width * window_height) as f64;
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for index in indices {
+                let (x, y) = (a[index], b[index]);
+                sum_a += x;
+                sum_b += y;
+                sum_aa += x * x;
+                sum_bb += y * y;
+                sum_ab += x * y;
+            }
+            let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+            let variance_a = sum_aa / count - mean_a * mean_a;
+            let variance_b = sum_bb / count - mean_b * mean_b;
+            let covariance = sum_ab / count - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (variance_a + variance_b + C2));
+            windows += 1;
+        }
+    }
+    total / windows.max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat(width: u32, height: u32, value: u8) -> RgbImage {
+        RgbImage::from_pixel(width, height, Rgb([value; 3]))
+    }
+
+    #[test]
+    fn identical_images_are_perfect() {
+        let image = RgbImage::from_fn(16, 16, |x, y| Rgb([(x * 16) as u8, (y * 16) as u8, 90]));
+        let quality = measure(&image, &image);
+        assert_eq!(quality.psnr, f64::INFINITY);
+        assert!((quality.ssim - 1.0).abs() < 1e-9);
+        assert_eq!(quality.mean_delta_e, 0.0);
+    }
+
+    #[test]
+    fn flat_offsets_give_known_scores() {
+        let quality = measure(&flat(16, 16, 100), &flat(16, 16, 110));
+        // Every sample is 10 off: MSE 100.
+        assert!((quality.psnr - 10.0 * (255.0f64 * 255.0 / 100.0).log10()).abs() < 1e-9);
+        // Flat windows have no variance, so only the luminance term remains.
+        let c1 = (0.01 * 255.0) * (0.01 * 255.0);
+        let luminance = (2.0 * 100.0 * 110.0 + c1) / (100.0 * 100.0 + 110.0 * 110.0 + c1);
+        assert!((quality.ssim - luminance).abs() < 1e-6);
+        assert!(quality.mean_delta_e > 0.0);
+
+        let opposite = measure(&flat(4, 4, 0), &flat(4, 4, 255));
+        assert!((opposite.psnr - 0.0).abs() < 1e-9);
+        assert!((opposite.mean_delta_e - 100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn cells_shrink_by_averaging() {
+        let checker = |x: u32, y: u32| if (x + y).is_multiple_of(2) { 0 } else { 200 };
+        let image = RgbImage::from_fn(8, 8, |x, y| Rgb([checker(x, y); 3]));
+        let shrunk = shrink_cells(&image, 8, 4);
+        assert_eq!(shrunk.dimensions(), (4, 4));
+        assert!(shrunk.pixels().all(|pixel| pixel.0 == [100; 3]));
+        // Smaller cells than the target are kept at one pixel per source pixel.
+        assert_eq!(shrink_cells(&image, 2, 2), image);
+    }
+
+    #[test]
+    fn bands_add_up_to_the_whole_image() {
+        let source = RgbImage::from_fn(24, 24, |x, y| Rgb([(x * 10) as u8, (y * 10) as u8, 0]));
+        let mosaic = RgbImage::from_fn(24, 24, |x, y| Rgb([(y * 10) as u8, 40, (x * 5) as u8]));
+        let mut whole = Comparison::new((3, 3), 8);
+        whole.add_band(0, &source, &mosaic);
+        let mut banded = Comparison::new((3, 3), 8);
+        for first_row in 0..3 {
+            let view = |image: &RgbImage| {
+                imageops::crop_imm(image, 0, first_row * 8, 24, 8).to_image()
+            };
+            banded.add_band(first_row, &view(&source), &view(&mosaic));
+        }
+        assert_eq!(whole.quality(), banded.quality());
+        assert_eq!(whole.heatmap(), banded.heatmap());
+    }
+
+    #[test]
+    fn heatmap_runs_from_black_to_white() {
+        let mut comparison = Comparison::new((2, 1), 4);
+        let source = RgbImage::from_fn(8, 4, |x, _| Rgb([if x < 4 { 80 } else { 0 }; 3]));
+        let mosaic = RgbImage::from_fn(8, 4, |x, _| Rgb([if x < 4 { 80 } else { 255 }; 3]));
+        comparison.add_band(0, &source, &mosaic);
+        let heat = comparison.heatmap();
+        assert_eq!(heat.get_pixel(0, 0).0, [0, 0, 0]);
+        assert_eq!(heat.get_pixel(7, 3).0, [255, 255, 255]);
+    }
+}
@@ -1,12 +1,29 @@
+pub mod analysis;
 pub mod catalog;
+pub mod compare;
+pub mod detail;
+pub mod dither;
 pub mod image_utils;
+pub mod label;
+pub mod mask;
+pub mod metrics;
 pub mod mosaic;
+pub mod sampling;
+pub mod scan;
+pub mod search;
+pub mod structure;
+#[cfg(test)]
+mod testing;
 pub mod traits;
 
 use crate::app::traits::{CatalogStore, ImageIo};
-use crate::domain::{Catalog, MosaicResult, MosaicSpec, Tile};
+use crate::domain::{
+    Catalog, CatalogBundle, CatalogStats, CompareResult, CompareSpec, GapReport, GapSpec,
+    HashKind, ImportOptions, ImportReport, MosaicResult, MosaicSpec, RenderSpec, SearchHit,
+    SearchQuery, Tile,
+};
 use crate::error::AppResult;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub struct App<C: CatalogStore, I: ImageIo> {
     catalog_store: C,
@@ -21,8 +38,8 @@ impl<C: CatalogStore, I: ImageIo> App<C, I> {
         }
     }
 
-    pub fn catalog_add(&self, path: &Path) -> AppResult<Vec<Tile>> {
-        catalog::add_tiles(&self.catalog_store, &self.image_io, path)
+    pub fn catalog_add(&self, path: &Path, options: &ImportOptions) -> AppResult<ImportReport> {
+        catalog::add_tiles(&self.catalog_store, &self.image_io, path, options)
     }
 
     pub fn catalog_list(&self) -> AppResult<Catalog> {
@@ -33,7 +50,51 @@ impl<C: CatalogStore, I: ImageIo> App<C, I> {
         catalog::remove_tile(&self.catalog_store, id)
     }
 
+    pub fn catalog_tag(&self, id: &str, add: &[String], remove: &[String]) -> AppResult<Tile> {
+        catalog::tag_tile(&self.catalog_store, id, add, remove)
+    }
+
+    pub fn catalog_search(&self, query: &SearchQuery) -> AppResult<Vec<SearchHit>> {
+        search::search_tiles(&self.catalog_store, query)
+    }
+
+    pub fn catalog_dedupe(&self, kind: HashKind, threshold: u32) -> AppResult<Vec<Vec<Tile>>> {
+        catalog::find_near_duplicates(&self.catalog_store, &self.image_io, kind, threshold)
+    }
+
+    pub fn catalog_stats(&self, levels: u8) -> AppResult<CatalogStats> {
+        analysis::catalog_stats(&self.catalog_store, levels)
+    }
+
+    pub fn catalog_gaps(&self, spec: &GapSpec) -> AppResult<GapReport> {
+        analysis::find_gaps(&self.catalog_store, &self.image_io, spec)
+    }
+
+    pub fn catalog_set_root(&self, root: &Path) -> AppResult<(PathBuf, usize)> {
+        catalog::set_root(&self.catalog_store, root)
+    }
+
+    pub fn catalog_relocate(&self, old_root: &Path, new_root: &Path) -> AppResult<usize> {
+        catalog::relocate(&self.catalog_store, old_root, new_root)
+    }
+
+    pub fn catalog_export(&self, thumbnail_size: Option<u32>) -> AppResult<CatalogBundle> {
+        catalog::export_catalog(&self.catalog_store, &self.image_io, thumbnail_size)
+    }
+
+    pub fn catalog_import(&self, bundle: CatalogBundle, root: Option<&Path>) -> AppResult<Vec<Tile>> {
+        catalog::import_catalog(&self.catalog_store, &self.image_io, bundle, root)
+    }
+
     pub fn generate_mosaic(&self, spec: &MosaicSpec) -> AppResult<MosaicResult> {
         mosaic::generate_mosaic(&self.catalog_store, &self.image_io, spec)
     }
+
+    pub fn render_manifest(&self, spec: &RenderSpec) -> AppResult<MosaicResult> {
+        mosaic::render_manifest(&self.catalog_store, &self.image_io, spec)
+    }
+
+    pub fn compare_settings(&self, spec: &CompareSpec) -> AppResult<CompareResult> {
+        compare::compare_settings(&self.catalog_store, &self.image_io, spec)
+    }
 }
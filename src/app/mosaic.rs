@@ -1,12 +1,135 @@
-use crate::app::image_utils::{average_color, color_distance};
+use crate::app::catalog::tile_id_for_path;
+use crate::app::detail::{importance_map, peak_importance};
+use crate::app::dither::Diffusion;
+use crate::app::image_utils::{
+    average_color, color_distance, flatten, resize_exact, srgb_to_lab,
+};
+use crate::app::mask::cell_regions;
+use crate::app::metrics::Comparison;
+use crate::app::sampling::sample;
+use crate::app::structure::{luma_pattern, pattern_distance, LumaPattern};
 use crate::app::traits::{CatalogStore, ImageIo};
-use crate::domain::{Catalog, MosaicResult, MosaicSpec, TilesSource, Tile};
+use crate::domain::{
+    Catalog, CellRegion, ColorSpace, DetailSource, DetailSpec, Manifest, MatchMetric, MosaicResult,
+    MosaicSpec, OutputOptions, Placement, RasterLayout, RenderSpec, Tile, TileTransform,
+    TilesSource,
+};
 use crate::error::{AppError, AppResult};
-use image::{imageops, DynamicImage, GenericImageView, RgbImage};
-use std::path::Path;
+use image::{DynamicImage, GenericImageView, GrayImage, Rgb, Rgba, RgbaImage, RgbImage};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
+/// Band size used when no memory limit is given.
+const DEFAULT_BAND_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Squared ratio of an sRGB channel's range to L*'s.
+const LAB_TO_RGB_SCALE: f32 = (255.0 / 100.0) * (255.0 / 100.0);
+
+/// How tile images are prepared for drawing.
+#[derive(Clone, Copy)]
+struct TileStyle {
+    width: u32,
+    height: u32,
+    matte: [u8; 3],
+    space: ColorSpace,
+}
+
+/// A manifest placement tied to an image sized to its whole span, or to its
+/// part of a subdivided cell.
+struct PlacedTile {
+    region: CellRegion,
+    /// Pixel offset within the cell; non-zero only for part of a subdivided cell.
+    offset: (u32, u32),
+    tile: usize,
+    adjustment: Option<[i16; 3]>,
+}
+
+/// How much of a grid cell the manifest's placements cover.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Coverage {
+    Empty,
+    /// Parts of a subdivided cell, which may leave gaps.
+    Partial,
+    Full,
+}
+
+/// The tiles each cell may be matched with.
+struct TilePools {
+    /// Tile indices per pool: `MosaicSpec::tiles_source` first, then one per mask region.
+    pools: Vec<Vec<usize>>,
+    /// Pool of each cell in row-major order; without a mask every cell uses the first.
+    cells: Option<Vec<usize>>,
+    grid_width: u32,
+}
+
+/// The spec's mask, pins, bans and detail settings, checked against the grid
+/// and tied to loaded tiles.
+struct CellRules {
+    pools: TilePools,
+    pins: Vec<PinnedTile>,
+    /// Indices of banned tiles and where they are banned; `None` is everywhere.
+    bans: Vec<(usize, Option<CellRegion>)>,
+    detail: Option<Detail>,
+    /// Each tile's luma pattern, when structure is matched on.
+    patterns: Option<Vec<LumaPattern>>,
+    /// Each tile's average in L*a*b*, when matching by ΔE.
+    labs: Option<Vec<[f32; 3]>>,
+}
+
+/// What cell subdivision needs while rendering.
+struct Detail {
+    levels: u32,
+    tolerance: Option<f32>,
+    /// One pixel per smallest cell.
+    importance: GrayImage,
+    /// Edge length of the smallest cells.
+    finest: u32,
+    /// Tile images for every level after the first: `scaled[level - 1][tile]`.
+    scaled: Vec<Vec<RgbImage>>,
+}
+
+/// Picks tiles for a cell, quartering it where the detail settings ask to.
+struct Matcher<'a> {
+    input: &'a DynamicImage,
+    spec: &'a MosaicSpec,
+    tiles: &'a [TileImage],
+    detail: Option<&'a Detail>,
+    patterns: Option<&'a [LumaPattern]>,
+    labs: Option<&'a [[f32; 3]]>,
+}
+
+/// A tile chosen for a square of the output, in pixels.
+struct Choice {
+    x: u32,
+    y: u32,
+    size: u32,
+    level: u32,
+    tile: usize,
+    target: [u8; 3],
+    /// The target with diffused error added, which the tile was matched to.
+    wanted: [f32; 3],
+}
+
+/// What rendering carries from one band to the next.
+struct BandState<'a> {
+    diffusion: Option<&'a mut Diffusion>,
+    comparison: &'a mut Comparison,
+    placements: Option<&'a mut Vec<Placement>>,
+}
+
+struct PinnedTile {
+    region: CellRegion,
+    /// The pinned tile, sized to cover the whole region.
+    tile: TileImage,
+    /// Average input color under the region.
+    target: [u8; 3],
+}
+
 struct TileImage {
+    id: String,
+    path: PathBuf,
     avg_color: [u8; 3],
     image: RgbImage,
 }
@@ -21,74 +144,998 @@ pub fn generate_mosaic<C: CatalogStore, I: ImageIo>(
             "tile size must be greater than zero".to_string(),
         ));
     }
+    // Rejects unwritable outputs before any tiles are loaded.
+    let alpha = spec.preserve_transparency;
+    let streams = image_io.can_stream(&spec.output, &spec.output_options, alpha)?;
+    if let Some(heatmap) = &spec.heatmap {
+        image_io.can_stream(heatmap, &OutputOptions::default(), false)?;
+    }
 
     let input = image_io.read(&spec.input)?;
-    let grid_width = input.width() / spec.tile_size;
-    let grid_height = input.height() / spec.tile_size;
+    let (grid_width, grid_height) = grid_size(&input, spec.tile_size)?;
 
-    if grid_width == 0 || grid_height == 0 {
-        return Err(AppError::InvalidInput(
-            "input image is smaller than the tile size".to_string(),
-        ));
-    }
-
-    let tiles = match &spec.tiles_source {
-        TilesSource::Catalog => build_tiles_from_catalog(catalog_store, image_io, spec.tile_size)?,
-        TilesSource::Directory(path) => build_tiles_from_dir(image_io, path, spec.tile_size)?,
+    let style = TileStyle {
+        width: spec.tile_size,
+        height: spec.tile_size,
+        matte: spec.matte,
+        space: spec.color_space,
     };
+    let grid = (grid_width, grid_height);
+    let (tiles, pools) = load_tile_pools(catalog_store, image_io, spec, &input, grid, style)?;
+    let rules = resolve_rules(image_io, spec, &input, &tiles, pools, grid)?;
+    let width = grid_width * spec.tile_size;
+    let height = grid_height * spec.tile_size;
+    let band_rows = band_rows(spec, &input, tiles.len(), (grid_width, grid_height), streams)?;
 
-    if tiles.is_empty() {
-        return Err(AppError::InvalidInput(
-            "no tiles available for mosaic generation".to_string(),
-        ));
+    let mut placements = Vec::new();
+    let mut diffusion = spec.dither.map(|dither| Diffusion::new(dither, grid));
+    let mut comparison = Comparison::new(grid, spec.tile_size);
+    let mut bands = (0..grid_height)
+        .step_by(band_rows as usize)
+        .map(|first_row| {
+            let rows = band_rows.min(grid_height - first_row);
+            let state = BandState {
+                diffusion: diffusion.as_mut(),
+                comparison: &mut comparison,
+                placements: spec.manifest.is_some().then_some(&mut placements),
+            };
+            render_band(&input, &tiles, &rules, spec, grid_width, (first_row, rows), state)
+        });
+    let layout = RasterLayout {
+        width,
+        height,
+        alpha: spec.preserve_transparency,
+    };
+    image_io.write_bands(&spec.output, &layout, &mut bands, &spec.output_options)?;
+    drop(bands);
+    if let Some(heatmap) = &spec.heatmap {
+        image_io.write_rgb(heatmap, &comparison.heatmap(), &OutputOptions::default())?;
     }
 
-    let mut output = RgbImage::new(grid_width * spec.tile_size, grid_height * spec.tile_size);
+    let seed = spec.sampling.map(|sampling| sampling.seed);
+    let manifest = spec.manifest.is_some().then(|| Manifest {
+        input: spec.input.clone(),
+        output: spec.output.clone(),
+        tile_size: spec.tile_size,
+        grid_width,
+        grid_height,
+        color_space: spec.color_space,
+        matte: spec.matte,
+        transparent: spec.preserve_transparency,
+        seed,
+        placements,
+    });
 
-    for tile_y in 0..grid_height {
-        for tile_x in 0..grid_width {
-            let x = tile_x * spec.tile_size;
-            let y = tile_y * spec.tile_size;
-            let region = input.view(x, y, spec.tile_size, spec.tile_size).to_image();
-            let region_img = DynamicImage::ImageRgba8(region);
-            let region_avg = average_color(&region_img);
+    Ok(MosaicResult {
+        output: spec.output.clone(),
+        tiles_used: tiles.len(),
+        grid_width,
+        grid_height,
+        bands: grid_height.div_ceil(band_rows),
+        seed,
+        quality: Some(comparison.quality()),
+        manifest,
+    })
+}
 
-            let best_tile = tiles
-                .iter()
-                .min_by_key(|tile| color_distance(tile.avg_color, region_avg))
-                .expect("tiles are not empty");
+/// Draws the placements of a saved manifest again, at any tile size and with
+/// optional grout. Tiles are read from their recorded path, or through the
+/// catalog when that file has moved. Cells the manifest leaves out are
+/// transparent if it was rendered with transparency and matte otherwise;
+/// partial transparency within a cell is not kept, as the input is not reread.
+pub fn render_manifest<C: CatalogStore, I: ImageIo>(
+    catalog_store: &C,
+    image_io: &I,
+    spec: &RenderSpec,
+) -> AppResult<MosaicResult> {
+    let manifest = &spec.manifest;
+    let tile_size = spec.tile_size.unwrap_or(manifest.tile_size);
+    if tile_size == 0 {
+        return Err(AppError::InvalidInput(
+            "tile size must be greater than zero".to_string(),
+        ));
+    }
+    let (grid_width, grid_height) = (manifest.grid_width, manifest.grid_height);
+    if grid_width == 0 || grid_height == 0 {
+        return Err(AppError::InvalidInput("manifest has an empty grid".to_string()));
+    }
+    image_io.can_stream(&spec.output, &spec.output_options, manifest.transparent)?;
 
-            blit_tile(&mut output, &best_tile.image, x, y);
+    let pitch = tile_size + spec.grout;
+    let style = TileStyle {
+        width: tile_size,
+        height: tile_size,
+        matte: manifest.matte,
+        space: manifest.color_space,
+    };
+    let catalog = catalog_store.load()?;
+    let mut tiles = Vec::new();
+    let mut tile_indices: HashMap<(&str, &Path, u32, u32), usize> = HashMap::new();
+    let mut covered = vec![Coverage::Empty; grid_width as usize * grid_height as usize];
+    let mut placed = Vec::new();
+    for placement in &manifest.placements {
+        let (row, column) = (placement.row, placement.column);
+        let (row_span, column_span) = (placement.row_span, placement.column_span);
+        if row_span == 0 || column_span == 0 {
+            return Err(AppError::InvalidInput(format!(
+                "placement at row {row}, column {column} spans no cells"
+            )));
+        }
+        if row.saturating_add(row_span) > grid_height
+            || column.saturating_add(column_span) > grid_width
+        {
+            return Err(AppError::InvalidInput(format!(
+                "placement at row {row}, column {column} does not fit the \
+                 {grid_width} x {grid_height} grid"
+            )));
+        }
+        let region = CellRegion {
+            top: row,
+            left: column,
+            bottom: row + row_span - 1,
+            right: column + column_span - 1,
+        };
+        let part = sub_cell(placement, manifest.tile_size, tile_size)?;
+        let coverage = if part.is_some() {
+            Coverage::Partial
+        } else {
+            Coverage::Full
+        };
+        for cell_row in region.top..=region.bottom {
+            for cell_column in region.left..=region.right {
+                let cell = &mut covered[(cell_row * grid_width + cell_column) as usize];
+                if *cell == Coverage::Full || (*cell != Coverage::Empty && part.is_none()) {
+                    return Err(AppError::InvalidInput(format!(
+                        "cell at row {cell_row}, column {cell_column} is placed more than once"
+                    )));
+                }
+                *cell = coverage;
+            }
         }
+
+        let single = row_span == 1 && column_span == 1;
+        // Spanning tiles are drawn across the grout they cover.
+        let (left, top, width, height) = part.unwrap_or((
+            0,
+            0,
+            column_span * pitch - spec.grout,
+            row_span * pitch - spec.grout,
+        ));
+        let key = (
+            placement.tile_id.as_str(),
+            placement.tile_path.as_path(),
+            width,
+            height,
+        );
+        let index = match tile_indices.get(&key) {
+            Some(index) if single => *index,
+            _ if part.is_some() => {
+                // Parts of a cell are scaled down from the cell-sized tile, as
+                // when generating.
+                let mut tile = load_placed_tile(image_io, &catalog, placement, style)?;
+                let image = DynamicImage::ImageRgb8(tile.image);
+                tile.image = resize_exact(&image, width, height, style.space);
+                tiles.push(tile);
+                tile_indices.insert(key, tiles.len() - 1);
+                tiles.len() - 1
+            }
+            _ => {
+                let style = TileStyle {
+                    width,
+                    height,
+                    ..style
+                };
+                tiles.push(load_placed_tile(image_io, &catalog, placement, style)?);
+                if single {
+                    tile_indices.insert(key, tiles.len() - 1);
+                }
+                tiles.len() - 1
+            }
+        };
+        placed.push(PlacedTile {
+            region,
+            offset: (left, top),
+            tile: index,
+            adjustment: placement.color_adjustment,
+        });
     }
 
-    image_io.write_rgb(&spec.output, &output)?;
+    let width = grid_width * pitch + spec.grout;
+    let channels: u64 = if manifest.transparent { 4 } else { 3 };
+    let band_rows = default_band_rows(width as u64 * pitch as u64 * (3 + channels), grid_height);
+    let mut bands = (0..grid_height)
+        .step_by(band_rows as usize)
+        .map(|first_row| {
+            let rows = band_rows.min(grid_height - first_row);
+            let band = ManifestBand {
+                first_row,
+                rows,
+                tile_size,
+            };
+            Ok(render_manifest_band(spec, &tiles, &placed, &covered, band))
+        });
+    let layout = RasterLayout {
+        width,
+        height: grid_height * pitch + spec.grout,
+        alpha: manifest.transparent,
+    };
+    image_io.write_bands(&spec.output, &layout, &mut bands, &spec.output_options)?;
 
     Ok(MosaicResult {
         output: spec.output.clone(),
         tiles_used: tiles.len(),
         grid_width,
         grid_height,
+        bands: grid_height.div_ceil(band_rows),
+        seed: None,
+        quality: None,
+        manifest: None,
     })
 }
 
-fn build_tiles_from_catalog<C: CatalogStore, I: ImageIo>(
-    catalog_store: &C,
+/// The rectangle within its cell, at the rendered tile size, of a placement
+/// that covers only part of the cell, or `None` when it covers its whole span. Edges are
+/// scaled rather than sizes, so neighbouring parts still meet.
+fn sub_cell(
+    placement: &Placement,
+    manifest_size: u32,
+    tile_size: u32,
+) -> AppResult<Option<(u32, u32, u32, u32)>> {
+    let single = placement.row_span == 1 && placement.column_span == 1;
+    if !single || (placement.width >= manifest_size && placement.height >= manifest_size) {
+        return Ok(None);
+    }
+    let left = placement.x.checked_sub(placement.column * manifest_size);
+    let top = placement.y.checked_sub(placement.row * manifest_size);
+    let (Some(left), Some(top)) = (left, top) else {
+        return Err(sub_cell_error(placement));
+    };
+    let (right, bottom) = (left + placement.width, top + placement.height);
+    let empty = placement.width == 0 || placement.height == 0;
+    if empty || right > manifest_size || bottom > manifest_size {
+        return Err(sub_cell_error(placement));
+    }
+    let scale = |edge: u32| (edge as u64 * tile_size as u64 / manifest_size as u64) as u32;
+    let (left, top, right, bottom) = (scale(left), scale(top), scale(right), scale(bottom));
+    Ok(Some((left, top, (right - left).max(1), (bottom - top).max(1))))
+}
+
+fn sub_cell_error(placement: &Placement) -> AppError {
+    AppError::InvalidInput(format!(
+        "placement at row {}, column {} lies outside its cell",
+        placement.row, placement.column
+    ))
+}
+
+/// Loads a placed tile through the catalog entry with its id, or else from
+/// its recorded path, so editing `tile_id` is enough to swap a catalog tile.
+fn load_placed_tile<I: ImageIo>(
     image_io: &I,
+    catalog: &Catalog,
+    placement: &Placement,
+    style: TileStyle,
+) -> AppResult<TileImage> {
+    if let Some(tile) = catalog.tiles.iter().find(|tile| tile.id == placement.tile_id) {
+        return load_tile_image(image_io, catalog, tile, style);
+    }
+    if placement.tile_path.exists() {
+        let path = placement.tile_path.clone();
+        return load_path_image(image_io, placement.tile_id.clone(), path, style);
+    }
+    Err(AppError::CatalogNotFound(format!(
+        "{} ({})",
+        placement.tile_id,
+        placement.tile_path.display()
+    )))
+}
+
+#[derive(Clone, Copy)]
+struct ManifestBand {
+    first_row: u32,
+    rows: u32,
     tile_size: u32,
-) -> AppResult<Vec<TileImage>> {
-    let catalog = catalog_store.load()?;
-    build_tiles_from_catalog_data(image_io, &catalog, tile_size)
+}
+
+/// Renders grid rows of a manifest; the last band also carries the bottom grout line.
+fn render_manifest_band(
+    spec: &RenderSpec,
+    tiles: &[TileImage],
+    placed: &[PlacedTile],
+    covered: &[Coverage],
+    band: ManifestBand,
+) -> Vec<u8> {
+    let manifest = &spec.manifest;
+    let ManifestBand {
+        first_row,
+        rows,
+        tile_size,
+    } = band;
+    let grout = spec.grout;
+    let pitch = tile_size + grout;
+    let last = first_row + rows == manifest.grid_height;
+    let width = manifest.grid_width * pitch + grout;
+    let height = rows * pitch + if last { grout } else { 0 };
+    let mut image = RgbImage::from_pixel(width, height, Rgb(spec.grout_color));
+    let coverage = |row: u32, column: u32| covered[(row * manifest.grid_width + column) as usize];
+
+    let matte = RgbImage::from_pixel(tile_size, tile_size, Rgb(manifest.matte));
+    for row in 0..rows {
+        for column in 0..manifest.grid_width {
+            if coverage(first_row + row, column) != Coverage::Full {
+                blit_tile(&mut image, &matte, grout + column * pitch, grout + row * pitch);
+            }
+        }
+    }
+
+    let band_top = (first_row * pitch) as i64;
+    for tile in placed {
+        if tile.region.bottom < first_row || tile.region.top >= first_row + rows {
+            continue;
+        }
+        let x = grout + tile.region.left * pitch + tile.offset.0;
+        let top = (grout + tile.region.top * pitch + tile.offset.1) as i64 - band_top;
+        let source = &tiles[tile.tile].image;
+        match tile.adjustment {
+            Some(offset) => blit_clipped(&mut image, &adjust_colors(source, offset), x, top),
+            None => blit_clipped(&mut image, source, x, top),
+        }
+    }
+
+    if !manifest.transparent {
+        return image.into_raw();
+    }
+    // Grout stays opaque; only the inside of empty cells is cleared.
+    RgbaImage::from_fn(width, height, |x, y| {
+        let [r, g, b] = image.get_pixel(x, y).0;
+        let inside = x % pitch >= grout
+            && y % pitch >= grout
+            && x / pitch < manifest.grid_width
+            && y / pitch < rows;
+        let empty = inside && coverage(first_row + y / pitch, x / pitch) == Coverage::Empty;
+        Rgba([r, g, b, if empty { 0 } else { 255 }])
+    })
+    .into_raw()
+}
+
+/// Loads the tile sources cells need and groups the tiles into pools. Without
+/// a mask every cell draws from `spec.tiles_source`; with one, each cell draws
+/// from its region's source, and sources no cell uses are not loaded.
+fn load_tile_pools<C: CatalogStore, I: ImageIo>(
+    catalog_store: &C,
+    image_io: &I,
+    spec: &MosaicSpec,
+    input: &DynamicImage,
+    grid: (u32, u32),
+    style: TileStyle,
+) -> AppResult<(Vec<TileImage>, TilePools)> {
+    let mut sources: Vec<(&TilesSource, &[String])> = vec![(&spec.tiles_source, &[])];
+    let mut cells = None;
+    if let Some(mask) = &spec.mask {
+        sources.extend(mask.regions.iter().map(|region| (&region.source, &region.tags[..])));
+        let colors: Vec<[u8; 3]> = mask.regions.iter().map(|region| region.color).collect();
+        let image = image_io.read(&mask.path)?;
+        let regions = cell_regions(&image, &colors, input.dimensions(), spec.tile_size, grid);
+        let pools: Vec<usize> = regions
+            .into_iter()
+            .map(|region| region.map_or(0, |index| index + 1))
+            .collect();
+        cells = Some(pools);
+    }
+    let mut used = vec![cells.is_none(); sources.len()];
+    for pool in cells.iter().flatten() {
+        used[*pool] = true;
+    }
+
+    let mut tiles = Vec::new();
+    let mut catalog: Option<(Catalog, usize)> = None;
+    let mut directories: HashMap<&Path, Range<usize>> = HashMap::new();
+    let mut pools = Vec::with_capacity(sources.len());
+    for (index, (source, tags)) in sources.into_iter().enumerate() {
+        if !used[index] {
+            pools.push(Vec::new());
+            continue;
+        }
+        let pool: Vec<usize> = match source {
+            TilesSource::Catalog => {
+                if catalog.is_none() {
+                    let loaded = catalog_store.load()?;
+                    let start = tiles.len();
+                    tiles.extend(build_tiles_from_catalog_data(image_io, &loaded, style)?);
+                    catalog = Some((loaded, start));
+                }
+                let (catalog, start) = catalog.as_ref().expect("catalog was just loaded");
+                catalog
+                    .tiles
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, tile)| tags.iter().all(|tag| tile.tags.contains(tag)))
+                    .map(|(offset, _)| start + offset)
+                    .collect()
+            }
+            TilesSource::Directory(path) => match directories.get(path.as_path()) {
+                Some(range) => range.clone().collect(),
+                None => {
+                    let start = tiles.len();
+                    tiles.extend(build_tiles_from_dir(image_io, path, style)?);
+                    directories.insert(path, start..tiles.len());
+                    (start..tiles.len()).collect()
+                }
+            },
+        };
+
+        if pool.is_empty() {
+            let message = match &spec.mask {
+                Some(mask) if index > 0 => {
+                    let [r, g, b] = mask.regions[index - 1].color;
+                    format!("mask region #{r:02x}{g:02x}{b:02x} has no tiles")
+                }
+                _ => "no tiles available for mosaic generation".to_string(),
+            };
+            return Err(AppError::InvalidInput(message));
+        }
+        pools.push(pool);
+    }
+
+    let grid_width = grid.0;
+    Ok((
+        tiles,
+        TilePools {
+            pools,
+            cells,
+            grid_width,
+        },
+    ))
+}
+
+impl TilePools {
+    fn candidates(&self, row: u32, column: u32) -> &[usize] {
+        let pool = match &self.cells {
+            Some(cells) => cells[(row * self.grid_width + column) as usize],
+            None => 0,
+        };
+        &self.pools[pool]
+    }
+}
+
+/// Checks pins and bans against the grid and the loaded tiles, and loads each
+/// pinned tile at the size of its region.
+fn resolve_rules<I: ImageIo>(
+    image_io: &I,
+    spec: &MosaicSpec,
+    input: &DynamicImage,
+    tiles: &[TileImage],
+    pools: TilePools,
+    (grid_width, grid_height): (u32, u32),
+) -> AppResult<CellRules> {
+    // The same file can be loaded for more than one pool.
+    let find = |tile_id: &str, role: &str| {
+        let indices: Vec<usize> = (0..tiles.len()).filter(|i| tiles[*i].id == tile_id).collect();
+        if indices.is_empty() {
+            return Err(AppError::InvalidInput(format!(
+                "{role} tile {tile_id} is not among the mosaic's tiles"
+            )));
+        }
+        Ok(indices)
+    };
+    let check_region = |region: &CellRegion| {
+        if region.bottom >= grid_height || region.right >= grid_width {
+            return Err(AppError::InvalidInput(format!(
+                "region {region} is outside the {grid_width} x {grid_height} grid"
+            )));
+        }
+        Ok(())
+    };
+
+    let mut pins: Vec<PinnedTile> = Vec::new();
+    for pin in &spec.pins {
+        check_region(&pin.region)?;
+        if let Some(other) = pins.iter().find(|other| other.region.overlaps(&pin.region)) {
+            return Err(AppError::InvalidInput(format!(
+                "pinned regions {} and {} overlap",
+                other.region, pin.region
+            )));
+        }
+        let source = &tiles[find(&pin.tile_id, "pinned")?[0]];
+        let style = TileStyle {
+            width: pin.region.columns() * spec.tile_size,
+            height: pin.region.rows() * spec.tile_size,
+            matte: spec.matte,
+            space: spec.color_space,
+        };
+        let tile = load_path_image(image_io, source.id.clone(), source.path.clone(), style)?;
+        let target = region_color(input, spec, pin.region).unwrap_or(tile.avg_color);
+        pins.push(PinnedTile {
+            region: pin.region,
+            tile,
+            target,
+        });
+    }
+
+    let mut bans = Vec::new();
+    for ban in &spec.bans {
+        if let Some(region) = &ban.region {
+            check_region(region)?;
+        }
+        for index in find(&ban.tile_id, "banned")? {
+            bans.push((index, ban.region));
+        }
+    }
+
+    let detail = match &spec.detail {
+        Some(detail) => Some(prepare_detail(image_io, spec, input, tiles, detail)?),
+        None => None,
+    };
+    let patterns = spec.structure_weight.map(|_| {
+        tiles
+            .iter()
+            .map(|tile| luma_pattern(&DynamicImage::ImageRgb8(tile.image.clone())))
+            .collect()
+    });
+    let labs = (spec.metric == MatchMetric::Lab)
+        .then(|| tiles.iter().map(|tile| srgb_to_lab(tile.avg_color)).collect());
+    Ok(CellRules {
+        pools,
+        pins,
+        bans,
+        detail,
+        patterns,
+        labs,
+    })
+}
+
+/// Builds the importance map and scales every tile down to each finer level.
+fn prepare_detail<I: ImageIo>(
+    image_io: &I,
+    spec: &MosaicSpec,
+    input: &DynamicImage,
+    tiles: &[TileImage],
+    detail: &DetailSpec,
+) -> AppResult<Detail> {
+    let splits = detail.levels.saturating_sub(1);
+    if detail.levels == 0 || splits >= u32::BITS || !spec.tile_size.is_multiple_of(1 << splits) {
+        return Err(AppError::InvalidInput(format!(
+            "tile size {} cannot be halved into {} detail levels",
+            spec.tile_size, detail.levels
+        )));
+    }
+    let finest = spec.tile_size >> splits;
+    let map = match &detail.source {
+        DetailSource::Map(path) => Some(image_io.read(path)?),
+        DetailSource::Auto => None,
+    };
+
+    let scaled = (1..detail.levels)
+        .map(|level| {
+            let size = spec.tile_size >> level;
+            tiles
+                .iter()
+                .map(|tile| {
+                    let image = DynamicImage::ImageRgb8(tile.image.clone());
+                    resize_exact(&image, size, size, spec.color_space)
+                })
+                .collect()
+        })
+        .collect();
+    Ok(Detail {
+        levels: detail.levels,
+        tolerance: detail.tolerance,
+        importance: importance_map(input, map.as_ref(), finest),
+        finest,
+        scaled,
+    })
+}
+
+impl Matcher<'_> {
+    /// Picks the closest candidate for the `size` square at (`x`, `y`), or
+    /// splits the square into quarters and picks for each of them. With a
+    /// structure weight, candidates are also scored on how well their light and
+    /// dark areas line up with the square's. `error` is added to the square's
+    /// color before matching.
+    fn choose(
+        &self,
+        candidates: &[usize],
+        (x, y, size): (u32, u32, u32),
+        (level, error): (u32, [f32; 3]),
+        choices: &mut Vec<Choice>,
+    ) {
+        let Some(target) = pixel_color(self.input, self.spec.into(), (x, y, size, size)) else {
+            return;
+        };
+        let wanted: [f32; 3] = std::array::from_fn(|channel| {
+            (target[channel] as f32 + error[channel]).clamp(0.0, 255.0)
+        });
+        let matched = wanted.map(|value| value.round() as u8);
+        let structure = self.patterns.zip(self.spec.structure_weight).map(|(patterns, weight)| {
+            let region = DynamicImage::ImageRgba8(self.input.view(x, y, size, size).to_image());
+            let region = flatten(region, self.spec.matte, self.spec.color_space);
+            (patterns, weight, luma_pattern(&region))
+        });
+        let matched_lab = self.labs.map(|_| srgb_to_lab(matched));
+        let score = |index: usize| {
+            let tile_lab = self.labs.map(|labs| labs[index]);
+            let color =
+                match_distance((self.tiles[index].avg_color, tile_lab), (matched, matched_lab));
+            let shape = structure.as_ref().map_or(0.0, |(patterns, weight, pattern)| {
+                weight * pattern_distance(&patterns[index], pattern)
+            });
+            color + shape
+        };
+        let mut scored: Vec<(usize, f32)> =
+            candidates.iter().map(|index| (*index, score(*index))).collect();
+        let tile = match self.spec.sampling {
+            Some(sampling) => sample(&mut scored, sampling, (x, y, size)),
+            None => {
+                let closest = scored.iter().min_by(|a, b| a.1.total_cmp(&b.1));
+                closest.expect("candidates are not empty").0
+            }
+        };
+        let distance = color_distance(self.tiles[tile].avg_color, matched);
+
+        if let Some(detail) = self.detail
+            && level + 1 < detail.levels
+        {
+            let finest = detail.finest;
+            let importance =
+                peak_importance(&detail.importance, x / finest, y / finest, size / finest);
+            let detailed = importance * detail.levels as f32 >= (level + 1) as f32;
+            let loose = detail
+                .tolerance
+                .is_some_and(|tolerance| (distance as f32).sqrt() > tolerance * (1.0 - importance));
+            if detailed || loose {
+                let half = size / 2;
+                for (dx, dy) in [(0, 0), (half, 0), (0, half), (half, half)] {
+                    let square = (x + dx, y + dy, half);
+                    self.choose(candidates, square, (level + 1, error), choices);
+                }
+                return;
+            }
+        }
+
+        choices.push(Choice {
+            x,
+            y,
+            size,
+            level,
+            tile,
+            target,
+            wanted,
+        });
+    }
+}
+
+/// Picks how many grid rows to render at a time. Without a memory limit bands
+/// are capped at `DEFAULT_BAND_BYTES`; with one, whatever the decoded input and
+/// tile images leave over goes to the band.
+fn band_rows(
+    spec: &MosaicSpec,
+    input: &DynamicImage,
+    tile_count: usize,
+    (grid_width, grid_height): (u32, u32),
+    streams: bool,
+) -> AppResult<u32> {
+    let channels: u64 = if spec.preserve_transparency { 4 } else { 3 };
+    let tile_pixels = spec.tile_size as u64 * spec.tile_size as u64;
+    // The RGB band plus the encoded copy handed to the writer, and the input
+    // and output copies flattened for the quality comparison.
+    let compared: u64 = if spec.preserve_transparency { 4 + 3 + 4 + 3 } else { 4 + 3 };
+    let row_bytes = grid_width as u64 * tile_pixels * (3 + channels + compared);
+
+    let Some(limit) = spec.memory_limit else {
+        return Ok(default_band_rows(row_bytes, grid_height));
+    };
+
+    let pinned_cells: u64 = spec
+        .pins
+        .iter()
+        .map(|pin| pin.region.rows() as u64 * pin.region.columns() as u64)
+        .sum();
+    // Each finer detail level holds every tile again at a quarter of the size.
+    let scaled_pixels: u64 = spec.detail.as_ref().map_or(0, |detail| {
+        (1..detail.levels.min(16)).map(|level| tile_pixels >> (2 * level)).sum()
+    });
+    let fixed = input.as_bytes().len() as u64
+        + Comparison::bytes((grid_width, grid_height), spec.tile_size)
+        + (tile_count as u64 + pinned_cells) * tile_pixels * 3
+        + tile_count as u64 * scaled_pixels * 3;
+    let budget = limit.saturating_sub(fixed);
+    if budget < row_bytes {
+        return Err(AppError::InvalidInput(format!(
+            "memory limit of {} is too low; input and tiles need {} plus {} per band row",
+            format_bytes(limit),
+            format_bytes(fixed),
+            format_bytes(row_bytes)
+        )));
+    }
+
+    let output_bytes = grid_height as u64 * grid_width as u64 * tile_pixels * channels;
+    if !streams && output_bytes > budget {
+        return Err(AppError::InvalidInput(format!(
+            "a {} output does not fit in the memory limit; write png or tiff to stream it",
+            format_bytes(output_bytes)
+        )));
+    }
+
+    Ok((budget / row_bytes).min(grid_height as u64) as u32)
+}
+
+fn default_band_rows(row_bytes: u64, grid_height: u32) -> u32 {
+    (DEFAULT_BAND_BYTES / row_bytes).clamp(1, grid_height as u64) as u32
+}
+
+/// Renders `rows` grid rows starting at `first_row` as interleaved samples,
+/// appending each drawn tile to `placements` when given. Pinned regions are
+/// drawn as they are; every other cell gets the closest tiles from its pool
+/// that are not banned there, split into smaller tiles where detail requires.
+/// With dithering, cells are visited in the diffusion's order and each passes
+/// the difference between its color and its tiles on to later cells.
+fn render_band(
+    input: &DynamicImage,
+    tiles: &[TileImage],
+    rules: &CellRules,
+    spec: &MosaicSpec,
+    grid_width: u32,
+    (first_row, rows): (u32, u32),
+    state: BandState,
+) -> AppResult<Vec<u8>> {
+    let BandState {
+        mut diffusion,
+        comparison,
+        mut placements,
+    } = state;
+    let size = spec.tile_size;
+    let mut band = RgbImage::new(grid_width * size, rows * size);
+    let matcher = Matcher {
+        input,
+        spec,
+        tiles,
+        detail: rules.detail.as_ref(),
+        patterns: rules.patterns.as_deref(),
+        labs: rules.labs.as_deref(),
+    };
+    let band_top = first_row * size;
+    let mut choices = Vec::new();
+    for row in 0..rows {
+        let grid_row = first_row + row;
+        for step in 0..grid_width {
+            let column = diffusion
+                .as_ref()
+                .map_or(step, |diffusion| diffusion.column(grid_row, step));
+            if let Some(pin) = rules.pins.iter().find(|pin| pin.region.contains(grid_row, column)) {
+                if (grid_row, column) == (pin.region.top, pin.region.left)
+                    && let Some(placements) = placements.as_deref_mut()
+                {
+                    let region = pin.region;
+                    let rect = (
+                        region.left * size,
+                        region.top * size,
+                        region.columns() * size,
+                        region.rows() * size,
+                    );
+                    placements.push(placement(region, rect, &pin.tile, pin.target));
+                }
+                continue;
+            }
+
+            let banned: Vec<usize> = rules
+                .bans
+                .iter()
+                .filter(|(_, region)| region.is_none_or(|region| region.contains(grid_row, column)))
+                .map(|(index, _)| *index)
+                .collect();
+            let candidates: Vec<usize> = rules
+                .pools
+                .candidates(grid_row, column)
+                .iter()
+                .copied()
+                .filter(|index| !banned.contains(index))
+                .collect();
+            if candidates.is_empty() {
+                return Err(AppError::InvalidInput(format!(
+                    "every tile is banned at row {grid_row}, column {column}"
+                )));
+            }
+
+            let error = diffusion
+                .as_ref()
+                .map_or([0.0; 3], |diffusion| diffusion.error(grid_row, column));
+            choices.clear();
+            let square = (column * size, grid_row * size, size);
+            matcher.choose(&candidates, square, (0, error), &mut choices);
+            if let Some(diffusion) = diffusion.as_deref_mut()
+                && !choices.is_empty()
+            {
+                // Each tile's share of the cell's error is weighted by its area.
+                let mut remaining = [0.0; 3];
+                for choice in &choices {
+                    let share = (choice.size * choice.size) as f32 / (size * size) as f32;
+                    let avg_color = tiles[choice.tile].avg_color;
+                    for (channel, value) in remaining.iter_mut().enumerate() {
+                        *value += (choice.wanted[channel] - avg_color[channel] as f32) * share;
+                    }
+                }
+                diffusion.spread(grid_row, column, remaining);
+            }
+            for choice in &choices {
+                let tile = &tiles[choice.tile];
+                let image = match &rules.detail {
+                    Some(detail) if choice.level > 0 => {
+                        &detail.scaled[choice.level as usize - 1][choice.tile]
+                    }
+                    _ => &tile.image,
+                };
+                blit_tile(&mut band, image, choice.x, choice.y - band_top);
+                if let Some(placements) = placements.as_deref_mut() {
+                    let cell = CellRegion::cell(grid_row, column);
+                    let rect = (choice.x, choice.y, choice.size, choice.size);
+                    placements.push(placement(cell, rect, tile, choice.target));
+                }
+            }
+        }
+    }
+
+    for pin in &rules.pins {
+        if pin.region.bottom >= first_row && pin.region.top < first_row + rows {
+            let top = (pin.region.top as i64 - first_row as i64) * size as i64;
+            blit_clipped(&mut band, &pin.tile.image, pin.region.left * size, top);
+        }
+    }
+
+    // Both are compared as displayed, over the matte.
+    let shown = |image: DynamicImage| flatten(image, spec.matte, spec.color_space).to_rgb8();
+    let source = input.view(0, band_top, band.width(), band.height()).to_image();
+    if !spec.preserve_transparency {
+        comparison.add_band(first_row, &shown(DynamicImage::ImageRgba8(source)), &band);
+        return Ok(band.into_raw());
+    }
+    // Each output pixel keeps the alpha of the input pixel it covers.
+    let output = RgbaImage::from_fn(band.width(), band.height(), |x, y| {
+        let [r, g, b] = band.get_pixel(x, y).0;
+        Rgba([r, g, b, source.get_pixel(x, y)[3]])
+    });
+    comparison.add_band(
+        first_row,
+        &shown(DynamicImage::ImageRgba8(source)),
+        &shown(DynamicImage::ImageRgba8(output.clone())),
+    );
+    Ok(output.into_raw())
+}
+
+/// A placement covering `region`; `rect` is the pixel rectangle drawn, which
+/// is smaller than the region for tiles of a subdivided cell.
+fn placement(
+    region: CellRegion,
+    (x, y, width, height): (u32, u32, u32, u32),
+    tile: &TileImage,
+    target: [u8; 3],
+) -> Placement {
+    Placement {
+        row: region.top,
+        column: region.left,
+        row_span: region.rows(),
+        column_span: region.columns(),
+        x,
+        y,
+        width,
+        height,
+        tile_id: tile.id.clone(),
+        tile_path: tile.path.clone(),
+        cell_color: target,
+        tile_color: tile.avg_color,
+        distance: (color_distance(tile.avg_color, target) as f32).sqrt(),
+        transform: TileTransform::Stretch,
+        color_adjustment: None,
+    }
+}
+
+fn region_color(input: &DynamicImage, spec: &MosaicSpec, region: CellRegion) -> Option<[u8; 3]> {
+    let size = spec.tile_size;
+    let rect = (
+        region.left * size,
+        region.top * size,
+        region.columns() * size,
+        region.rows() * size,
+    );
+    pixel_color(input, spec.into(), rect)
+}
+
+/// How input pixels are turned into colors to match.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CellColors {
+    pub(crate) matte: [u8; 3],
+    pub(crate) space: ColorSpace,
+    /// Transparent areas stay transparent rather than showing the matte.
+    pub(crate) transparent: bool,
+}
+
+impl From<&MosaicSpec> for CellColors {
+    fn from(spec: &MosaicSpec) -> Self {
+        CellColors {
+            matte: spec.matte,
+            space: spec.color_space,
+            transparent: spec.preserve_transparency,
+        }
+    }
+}
+
+/// The color a rectangle of the input should be matched against, or `None`
+/// for one that is kept transparent. Kept transparent areas are matched on
+/// their visible pixels; otherwise the input is seen as displayed over the matte.
+pub(crate) fn pixel_color(
+    input: &DynamicImage,
+    colors: CellColors,
+    (x, y, width, height): (u32, u32, u32, u32),
+) -> Option<[u8; 3]> {
+    let region = input.view(x, y, width, height).to_image();
+    if colors.transparent {
+        if region.pixels().all(|pixel| pixel[3] == 0) {
+            return None;
+        }
+        return Some(average_color(&DynamicImage::ImageRgba8(region), colors.space));
+    }
+
+    let region = flatten(DynamicImage::ImageRgba8(region), colors.matte, colors.space);
+    Some(average_color(&region, colors.space))
+}
+
+/// Squared distance between a tile color and the color wanted for a cell, as
+/// tiles are ranked: on RGB values, or on Lab values when both are given.
+pub(crate) fn match_distance(
+    (color, lab): ([u8; 3], Option<[f32; 3]>),
+    (wanted, wanted_lab): ([u8; 3], Option<[f32; 3]>),
+) -> f32 {
+    match (lab, wanted_lab) {
+        // L* spans 0 to 100 where a channel spans 0 to 255; rescaling keeps
+        // tolerances and the structure weight on one footing.
+        (Some(lab), Some(wanted_lab)) => {
+            let squared: f32 = lab.iter().zip(wanted_lab).map(|(a, b)| (a - b) * (a - b)).sum();
+            squared * LAB_TO_RGB_SCALE
+        }
+        _ => color_distance(color, wanted) as f32,
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+/// Number of whole cells that fit in the input at the given tile size.
+pub(crate) fn grid_size(input: &DynamicImage, tile_size: u32) -> AppResult<(u32, u32)> {
+    if tile_size == 0 {
+        return Err(AppError::InvalidInput(
+            "tile size must be greater than zero".to_string(),
+        ));
+    }
+
+    let grid_width = input.width() / tile_size;
+    let grid_height = input.height() / tile_size;
+
+    if grid_width == 0 || grid_height == 0 {
+        return Err(AppError::InvalidInput(
+            "input image is smaller than the tile size".to_string(),
+        ));
+    }
+
+    Ok((grid_width, grid_height))
 }
 
 fn build_tiles_from_catalog_data<I: ImageIo>(
     image_io: &I,
     catalog: &Catalog,
-    tile_size: u32,
+    style: TileStyle,
 ) -> AppResult<Vec<TileImage>> {
     let mut tiles = Vec::new();
     for tile in &catalog.tiles {
-        tiles.push(load_tile_image(image_io, tile, tile_size)?);
+        tiles.push(load_tile_image(image_io, catalog, tile, style)?);
     }
     Ok(tiles)
 }
@@ -96,43 +1143,75 @@ fn build_tiles_from_catalog_data<I: ImageIo>(
 fn build_tiles_from_dir<I: ImageIo>(
     image_io: &I,
     path: &Path,
-    tile_size: u32,
+    style: TileStyle,
 ) -> AppResult<Vec<TileImage>> {
+    // Unlike catalog scans, every image is used, hidden and symlinked files too.
     let mut tiles = Vec::new();
-    for entry in WalkDir::new(path).into_iter().filter_map(Result::ok) {
+    let entries = WalkDir::new(path).sort_by_file_name().into_iter();
+    for entry in entries.filter_map(Result::ok) {
         let entry_path = entry.path();
-        if entry_path.is_file() && is_image_path(entry_path) {
-            let image = image_io.read(entry_path)?;
-            let avg_color = average_color(&image);
-            let resized = image.resize_exact(tile_size, tile_size, imageops::FilterType::Triangle);
-            tiles.push(TileImage {
-                avg_color,
-                image: resized.to_rgb8(),
-            });
+        if entry_path.is_file() && image_io.detect_format(entry_path).is_some() {
+            let id = tile_id_for_path(entry_path);
+            tiles.push(load_path_image(image_io, id, entry_path.to_path_buf(), style)?);
         }
     }
 
     Ok(tiles)
 }
 
+fn load_path_image<I: ImageIo>(
+    image_io: &I,
+    id: String,
+    path: PathBuf,
+    style: TileStyle,
+) -> AppResult<TileImage> {
+    let image = flatten(image_io.read(&path)?, style.matte, style.space);
+    Ok(TileImage {
+        id,
+        avg_color: average_color(&image, style.space),
+        image: resize_exact(&image, style.width, style.height, style.space),
+        path,
+    })
+}
+
 fn load_tile_image<I: ImageIo>(
     image_io: &I,
+    catalog: &Catalog,
     tile: &Tile,
-    tile_size: u32,
+    style: TileStyle,
 ) -> AppResult<TileImage> {
-    let image = image_io.read(&tile.path)?;
-    let resized = image.resize_exact(tile_size, tile_size, imageops::FilterType::Triangle);
+    let space = style.space;
+    let path = catalog.resolve_path(&tile.path);
+    let path = match &tile.thumbnail {
+        Some(thumbnail) if !path.exists() => thumbnail.clone(),
+        _ => path,
+    };
+    let image = image_io.read(&path)?;
+    // A stored average ignores the matte and may be in another space; either
+    // way it would not describe the tile as it is drawn.
+    let has_alpha = image.color().has_alpha();
+    let image = flatten(image, style.matte, space);
+    let avg_color = if !has_alpha && tile.metadata.color_space.unwrap_or_default() == space {
+        tile.avg_color
+    } else {
+        average_color(&image, space)
+    };
     Ok(TileImage {
-        avg_color: tile.avg_color,
-        image: resized.to_rgb8(),
+        id: tile.id.clone(),
+        path,
+        avg_color,
+        image: resize_exact(&image, style.width, style.height, space),
     })
 }
 
-fn is_image_path(path: &Path) -> bool {
-    path.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| matches!(ext.to_lowercase().as_str(), "png" | "jpg" | "jpeg" | "bmp" | "gif"))
-        .unwrap_or(false)
+fn adjust_colors(tile: &RgbImage, offset: [i16; 3]) -> RgbImage {
+    let mut adjusted = tile.clone();
+    for pixel in adjusted.pixels_mut() {
+        for (channel, delta) in pixel.0.iter_mut().zip(offset) {
+            *channel = (*channel as i16 + delta).clamp(0, 255) as u8;
+        }
+    }
+    adjusted
 }
 
 fn blit_tile(output: &mut RgbImage, tile: &RgbImage, x: u32, y: u32) {
@@ -143,3 +1222,82 @@ fn blit_tile(output: &mut RgbImage, tile: &RgbImage, x: u32, y: u32) {
         }
     }
 }
+
+/// Copies the rows of `image` that land inside `output`, placing the image's
+/// top edge `top` pixels below the output's (above it when negative).
+fn blit_clipped(output: &mut RgbImage, image: &RgbImage, x: u32, top: i64) {
+    for ty in 0..image.height() {
+        let y = top + ty as i64;
+        if y < 0 || y >= output.height() as i64 {
+            continue;
+        }
+        for tx in 0..image.width() {
+            output.put_pixel(x + tx, y as u32, *image.get_pixel(tx, ty));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placement(
+        (row, column): (u32, u32),
+        (x, y, width, height): (u32, u32, u32, u32),
+    ) -> Placement {
+        Placement {
+            row,
+            column,
+            row_span: 1,
+            column_span: 1,
+            x,
+            y,
+            width,
+            height,
+            tile_id: "tile".to_string(),
+            tile_path: PathBuf::from("tile.png"),
+            cell_color: [0, 0, 0],
+            tile_color: [0, 0, 0],
+            distance: 0.0,
+            transform: TileTransform::Stretch,
+            color_adjustment: None,
+        }
+    }
+
+    #[test]
+    fn whole_cells_and_spans_are_not_sub_cells() {
+        assert_eq!(sub_cell(&placement((2, 1), (16, 32, 16, 16)), 16, 10).unwrap(), None);
+        let span = Placement {
+            row_span: 2,
+            column_span: 2,
+            ..placement((0, 0), (0, 0, 32, 32))
+        };
+        assert_eq!(sub_cell(&span, 16, 10).unwrap(), None);
+    }
+
+    #[test]
+    fn sub_cell_edges_scale_so_neighbours_meet() {
+        let quarter = |dx: u32, dy: u32| {
+            sub_cell(&placement((1, 1), (16 + dx, 16 + dy, 8, 8)), 16, 10)
+        };
+        assert_eq!(quarter(0, 0).unwrap(), Some((0, 0, 5, 5)));
+        assert_eq!(quarter(8, 8).unwrap(), Some((5, 5, 5, 5)));
+
+        // 4 px pieces scale to 2.5 px; the shared edge rounds the same way for both.
+        let first = sub_cell(&placement((0, 0), (0, 0, 4, 4)), 16, 10).unwrap();
+        let second = sub_cell(&placement((0, 0), (4, 0, 4, 4)), 16, 10).unwrap();
+        assert_eq!(first, Some((0, 0, 2, 2)));
+        assert_eq!(second, Some((2, 0, 3, 2)));
+
+        // Never shrinks to nothing.
+        let tiny = sub_cell(&placement((0, 0), (2, 2, 2, 2)), 16, 2).unwrap();
+        assert_eq!(tiny, Some((0, 0, 1, 1)));
+    }
+
+    #[test]
+    fn sub_cells_must_lie_inside_their_cell() {
+        assert!(sub_cell(&placement((1, 1), (8, 16, 8, 8)), 16, 10).is_err());
+        assert!(sub_cell(&placement((1, 1), (28, 16, 8, 8)), 16, 10).is_err());
+        assert!(sub_cell(&placement((1, 1), (16, 16, 0, 8)), 16, 10).is_err());
+    }
+}
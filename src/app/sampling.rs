@@ -0,0 +1,93 @@
+use crate::domain::{SamplePool, SamplingSpec};
+
+/// Picks a tile from `scored` candidates, given as (tile index, score) with
+/// lower scores closer. The pick depends only on the seed and the square at
+/// (`x`, `y`), so it does not change with band size or visiting order.
+pub(crate) fn sample(
+    scored: &mut [(usize, f32)],
+    sampling: SamplingSpec,
+    (x, y, size): (u32, u32, u32),
+) -> usize {
+    scored.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let count = match sampling.pool {
+        SamplePool::Best(k) => (k as usize).min(scored.len()),
+        SamplePool::Within(tolerance) => {
+            let limit = scored[0].1.max(0.0).sqrt() + tolerance;
+            scored
+                .iter()
+                .take_while(|(_, score)| score.max(0.0).sqrt() <= limit)
+                .count()
+        }
+    };
+    let square = ((x as u64) << 40) ^ ((y as u64) << 16) ^ size as u64;
+    let roll = split_mix(sampling.seed ^ split_mix(square));
+    scored[(roll % count.max(1) as u64) as usize].0
+}
+
+/// SplitMix64's output function, which scrambles nearby inputs into
+/// unrelated values.
+fn split_mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    const SCORES: [(usize, f32); 5] = [(3, 226.0), (0, 100.0), (4, 400.0), (1, 200.0), (2, 225.0)];
+
+    fn picks(pool: SamplePool, seed: u64) -> Vec<usize> {
+        let spec = SamplingSpec { pool, seed };
+        (0..64)
+            .map(|cell| sample(&mut SCORES.to_vec(), spec, (cell % 8 * 16, cell / 8 * 16, 16)))
+            .collect()
+    }
+
+    fn distinct(picks: &[usize]) -> BTreeSet<usize> {
+        picks.iter().copied().collect()
+    }
+
+    #[test]
+    fn best_one_is_always_the_closest() {
+        assert!(picks(SamplePool::Best(1), 7).iter().all(|tile| *tile == 0));
+    }
+
+    #[test]
+    fn best_k_draws_from_the_k_closest() {
+        let drawn = picks(SamplePool::Best(3), 7);
+        assert_eq!(distinct(&drawn), BTreeSet::from([0, 1, 2]));
+        let all = picks(SamplePool::Best(10), 7);
+        assert_eq!(distinct(&all), BTreeSet::from([0, 1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn within_draws_from_tiles_near_the_closest() {
+        // The closest is 10 away, so a tolerance of 5 admits scores up to 15².
+        let drawn = picks(SamplePool::Within(5.0), 7);
+        assert_eq!(distinct(&drawn), BTreeSet::from([0, 1, 2]));
+        assert!(picks(SamplePool::Within(0.0), 7).iter().all(|tile| *tile == 0));
+    }
+
+    #[test]
+    fn picks_depend_only_on_seed_and_square() {
+        let pool = SamplePool::Best(5);
+        assert_eq!(picks(pool, 42), picks(pool, 42));
+        assert_ne!(picks(pool, 42), picks(pool, 43));
+
+        // Candidate order does not matter.
+        let spec = SamplingSpec { pool, seed: 42 };
+        let mut reversed = SCORES.to_vec();
+        reversed.reverse();
+        let square = (32, 48, 16);
+        assert_eq!(sample(&mut reversed, spec, square), sample(&mut SCORES.to_vec(), spec, square));
+    }
+
+    #[test]
+    fn recorded_seeds_keep_their_picks() {
+        assert_eq!(picks(SamplePool::Best(5), 42)[..8], [4, 3, 4, 1, 1, 3, 0, 0]);
+    }
+}
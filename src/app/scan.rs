@@ -0,0 +1,151 @@
+use crate::app::traits::ImageIo;
+use crate::domain::{ScanOptions, SymlinkPolicy};
+use crate::error::{AppError, AppResult};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::{DirEntry, WalkDir};
+
+const IGNORE_FILE: &str = ".mosaicignore";
+
+pub fn collect_image_paths<I: ImageIo>(
+    image_io: &I,
+    path: &Path,
+    options: &ScanOptions,
+) -> AppResult<Vec<PathBuf>> {
+    if path.is_file() {
+        if image_io.detect_format(path).is_some() {
+            return Ok(vec![path.to_path_buf()]);
+        }
+        return Err(AppError::InvalidInput(
+            "path is not an image file".to_string(),
+        ));
+    }
+
+    if !path.is_dir() {
+        return Err(AppError::InvalidInput(
+            "path is not a file or directory".to_string(),
+        ));
+    }
+
+    let include = build_globset(&options.include)?;
+    let exclude = build_globset(&options.exclude)?;
+    let mut ignore_files = IgnoreFiles::default();
+
+    let mut walker = WalkDir::new(path)
+        .follow_links(options.symlinks == SymlinkPolicy::Follow)
+        .sort_by_file_name();
+    if let Some(max_depth) = options.max_depth {
+        walker = walker.max_depth(max_depth);
+    }
+
+    let entries = walker.into_iter().filter_entry(|entry| {
+        if entry.depth() == 0 {
+            return true;
+        }
+        if !options.include_hidden && is_hidden(entry) {
+            return false;
+        }
+        if options.symlinks == SymlinkPolicy::Skip && entry.path_is_symlink() {
+            return false;
+        }
+        !exclude.is_match(relative_to(entry.path(), path))
+            && !ignore_files.is_ignored(path, entry.path())
+    });
+
+    let mut images = Vec::new();
+    for entry in entries.filter_map(Result::ok) {
+        let entry_path = entry.path();
+        if !entry_path.is_file() {
+            continue;
+        }
+        if !options.include.is_empty() && !include.is_match(relative_to(entry_path, path)) {
+            continue;
+        }
+        if image_io.detect_format(entry_path).is_none() {
+            continue;
+        }
+        images.push(entry_path.to_path_buf());
+    }
+
+    Ok(images)
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry
+        .file_name()
+        .to_str()
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+fn relative_to<'a>(path: &'a Path, base: &Path) -> &'a Path {
+    path.strip_prefix(base).unwrap_or(path)
+}
+
+/// Compiles gitignore-style globs: `*` stays within one path component and
+/// patterns without a `/` match at any depth.
+fn build_globset<S: AsRef<str>>(patterns: &[S]) -> AppResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.as_ref().trim().trim_end_matches('/');
+        if pattern.is_empty() {
+            continue;
+        }
+
+        let mut variants = vec![pattern.trim_start_matches('/').to_string()];
+        if !pattern.contains('/') {
+            variants.push(format!("**/{pattern}"));
+        }
+        for variant in variants {
+            let glob = GlobBuilder::new(&variant)
+                .literal_separator(true)
+                .build()
+                .map_err(|err| AppError::InvalidInput(format!("invalid glob {pattern}: {err}")))?;
+            builder.add(glob);
+        }
+    }
+
+    builder
+        .build()
+        .map_err(|err| AppError::InvalidInput(format!("invalid glob set: {err}")))
+}
+
+/// Lazily loaded `.mosaicignore` files, keyed by the directory they live in.
+#[derive(Default)]
+struct IgnoreFiles {
+    by_dir: HashMap<PathBuf, Option<GlobSet>>,
+}
+
+impl IgnoreFiles {
+    fn is_ignored(&mut self, root: &Path, path: &Path) -> bool {
+        let mut dir = path.parent();
+        while let Some(current) = dir {
+            if !current.starts_with(root) {
+                break;
+            }
+            if let Some(globs) = self.load(current)
+                && globs.is_match(relative_to(path, current))
+            {
+                return true;
+            }
+            dir = current.parent();
+        }
+        false
+    }
+
+    fn load(&mut self, dir: &Path) -> Option<&GlobSet> {
+        self.by_dir
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                let contents = fs::read_to_string(dir.join(IGNORE_FILE)).ok()?;
+                let patterns: Vec<&str> = contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .collect();
+                build_globset(&patterns).ok()
+            })
+            .as_ref()
+    }
+}
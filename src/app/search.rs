@@ -0,0 +1,204 @@
+use crate::app::image_utils::color_distance;
+use crate::app::traits::CatalogStore;
+use crate::domain::{SearchHit, SearchQuery, Tile};
+use crate::error::{AppError, AppResult};
+use globset::{Glob, GlobMatcher};
+
+pub fn search_tiles<C: CatalogStore>(
+    catalog_store: &C,
+    query: &SearchQuery,
+) -> AppResult<Vec<SearchHit>> {
+    let catalog = catalog_store.load()?;
+    let path_filter = query.path.as_deref().map(PathFilter::new).transpose()?;
+
+    let mut hits: Vec<SearchHit> = catalog
+        .tiles
+        .iter()
+        .filter(|tile| {
+            path_filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(tile))
+        })
+        .filter(|tile| query.tags.iter().all(|tag| tile.tags.contains(tag)))
+        .filter(|tile| matches_dimensions(tile, query))
+        .filter(|tile| matches_import_date(tile, query))
+        .map(|tile| SearchHit {
+            tile: tile.clone(),
+            distance: query
+                .color
+                .map(|color| (color_distance(color, tile.avg_color) as f32).sqrt()),
+        })
+        .collect();
+
+    if query.color.is_some() {
+        hits.sort_by(|a, b| {
+            a.distance
+                .unwrap_or_default()
+                .total_cmp(&b.distance.unwrap_or_default())
+        });
+    }
+    if let Some(limit) = query.limit {
+        hits.truncate(limit);
+    }
+
+    Ok(hits)
+}
+
+enum PathFilter {
+    Substring(String),
+    Glob(GlobMatcher),
+}
+
+impl PathFilter {
+    fn new(pattern: &str) -> AppResult<Self> {
+        if pattern.contains(['*', '?', '[']) {
+            let glob = Glob::new(pattern)
+                .map_err(|err| AppError::InvalidInput(format!("invalid glob {pattern}: {err}")))?;
+            Ok(PathFilter::Glob(glob.compile_matcher()))
+        } else {
+            Ok(PathFilter::Substring(pattern.to_string()))
+        }
+    }
+
+    fn matches(&self, tile: &Tile) -> bool {
+        match self {
+            PathFilter::Substring(value) => tile.path.to_string_lossy().contains(value.as_str()),
+            PathFilter::Glob(matcher) => matcher.is_match(&tile.path),
+        }
+    }
+}
+
+fn matches_dimensions(tile: &Tile, query: &SearchQuery) -> bool {
+    let within = |value: Option<u32>, min: Option<u32>, max: Option<u32>| {
+        if min.is_none() && max.is_none() {
+            return true;
+        }
+        value.is_some_and(|value| {
+            min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+        })
+    };
+
+    within(tile.metadata.width, query.min_width, query.max_width)
+        && within(tile.metadata.height, query.min_height, query.max_height)
+}
+
+fn matches_import_date(tile: &Tile, query: &SearchQuery) -> bool {
+    if query.imported_after.is_none() && query.imported_before.is_none() {
+        return true;
+    }
+
+    tile.metadata.imported_at.is_some_and(|imported| {
+        query.imported_after.is_none_or(|after| imported >= after)
+            && query.imported_before.is_none_or(|before| imported < before)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::testing::{tile, MemoryCatalog};
+
+    fn ids(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.tile.id.as_str()).collect()
+    }
+
+    #[test]
+    fn color_queries_rank_by_distance_before_the_limit() {
+        let catalog = MemoryCatalog::new(vec![
+            tile("white", "white.png", [255, 255, 255]),
+            tile("black", "black.png", [0, 0, 0]),
+            tile("gray", "gray.png", [100, 100, 100]),
+        ]);
+        let query = SearchQuery {
+            color: Some([10, 10, 10]),
+            ..SearchQuery::default()
+        };
+        let hits = search_tiles(&catalog, &query).unwrap();
+        assert_eq!(ids(&hits), ["black", "gray", "white"]);
+        assert_eq!(hits[0].distance, Some((300.0f32).sqrt()));
+
+        let limited = SearchQuery {
+            limit: Some(2),
+            ..query
+        };
+        assert_eq!(ids(&search_tiles(&catalog, &limited).unwrap()), ["black", "gray"]);
+
+        // Without a color, tiles keep catalog order and have no distance.
+        let unranked = SearchQuery {
+            limit: Some(2),
+            ..SearchQuery::default()
+        };
+        let hits = search_tiles(&catalog, &unranked).unwrap();
+        assert_eq!(ids(&hits), ["white", "black"]);
+        assert!(hits.iter().all(|hit| hit.distance.is_none()));
+    }
+
+    #[test]
+    fn paths_match_as_substrings_unless_they_look_like_globs() {
+        let substring = PathFilter::new("cat").unwrap();
+        let glob = PathFilter::new("photos/*.png").unwrap();
+        assert!(matches!(substring, PathFilter::Substring(_)));
+        assert!(matches!(glob, PathFilter::Glob(_)));
+
+        let cat = tile("cat", "photos/cat.png", [0, 0, 0]);
+        let nested = tile("nested", "photos/pets/dog.png", [0, 0, 0]);
+        let jpeg = tile("jpeg", "photos/catalog.jpg", [0, 0, 0]);
+        assert!(substring.matches(&cat) && substring.matches(&jpeg));
+        assert!(!substring.matches(&nested));
+        assert!(glob.matches(&cat) && !glob.matches(&jpeg));
+        assert!(matches!(PathFilter::new("[a"), Err(AppError::InvalidInput(_))));
+    }
+
+    fn measured(id: &str, width: Option<u32>, imported_at: Option<i64>) -> Tile {
+        let mut tile = tile(id, &format!("{id}.png"), [0, 0, 0]);
+        tile.metadata.width = width;
+        tile.metadata.height = width;
+        tile.metadata.imported_at = imported_at;
+        tile
+    }
+
+    #[test]
+    fn tiles_without_metadata_only_match_unbounded_queries() {
+        let catalog = MemoryCatalog::new(vec![
+            measured("small", Some(10), Some(100)),
+            measured("large", Some(50), Some(200)),
+            measured("unknown", None, None),
+        ]);
+        let search = |query: SearchQuery| ids(&search_tiles(&catalog, &query).unwrap()).join(" ");
+        assert_eq!(search(SearchQuery::default()), "small large unknown");
+        let wide = SearchQuery {
+            min_width: Some(20),
+            ..SearchQuery::default()
+        };
+        assert_eq!(search(wide), "large");
+        let narrow = SearchQuery {
+            max_height: Some(50),
+            ..SearchQuery::default()
+        };
+        assert_eq!(search(narrow), "small large");
+        let recent = SearchQuery {
+            imported_after: Some(0),
+            ..SearchQuery::default()
+        };
+        assert_eq!(search(recent), "small large");
+    }
+
+    #[test]
+    fn import_dates_are_half_open() {
+        let catalog = MemoryCatalog::new(vec![
+            measured("early", None, Some(100)),
+            measured("late", None, Some(200)),
+        ]);
+        let between = |after, before| {
+            let query = SearchQuery {
+                imported_after: Some(after),
+                imported_before: Some(before),
+                ..SearchQuery::default()
+            };
+            ids(&search_tiles(&catalog, &query).unwrap()).join(" ")
+        };
+        assert_eq!(between(100, 200), "early");
+        assert_eq!(between(100, 201), "early late");
+        assert_eq!(between(101, 201), "late");
+    }
+}
@@ -0,0 +1,65 @@
+use image::{imageops, DynamicImage};
+
+/// Edge length of the luma grid images are compared on.
+const PATTERN_SIZE: u32 = 4;
+
+/// Luma of an image shrunk to a small grid, less its mean, so only where an
+/// image is lighter or darker counts and not how bright it is overall.
+pub(crate) type LumaPattern = [f32; (PATTERN_SIZE * PATTERN_SIZE) as usize];
+
+pub(crate) fn luma_pattern(image: &DynamicImage) -> LumaPattern {
+    let small = imageops::resize(
+        &image.to_luma8(),
+        PATTERN_SIZE,
+        PATTERN_SIZE,
+        imageops::FilterType::Triangle,
+    );
+    let mut pattern = [0.0; (PATTERN_SIZE * PATTERN_SIZE) as usize];
+    for (value, pixel) in pattern.iter_mut().zip(small.pixels()) {
+        *value = pixel[0] as f32;
+    }
+    let mean = pattern.iter().sum::<f32>() / pattern.len() as f32;
+    for value in &mut pattern {
+        *value -= mean;
+    }
+    pattern
+}
+
+/// Mean squared luma difference between two patterns, times three so it is on
+/// the same scale as the squared RGB distance colors are matched by.
+pub(crate) fn pattern_distance(a: &LumaPattern, b: &LumaPattern) -> f32 {
+    let sum: f32 = a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum();
+    sum * 3.0 / a.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    fn gradient(offset: u8, rising: bool) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(16, 16, |x, _| {
+            let step = if rising { x } else { 15 - x } as u8;
+            Luma([offset + step * 8])
+        }))
+    }
+
+    #[test]
+    fn patterns_ignore_overall_brightness() {
+        let flat = DynamicImage::ImageLuma8(GrayImage::from_pixel(8, 8, Luma([90])));
+        assert!(luma_pattern(&flat).iter().all(|value| *value == 0.0));
+
+        let dark = luma_pattern(&gradient(0, true));
+        let light = luma_pattern(&gradient(100, true));
+        assert_eq!(pattern_distance(&dark, &light), 0.0);
+    }
+
+    #[test]
+    fn mirrored_patterns_are_far_apart() {
+        let rising = luma_pattern(&gradient(0, true));
+        let falling = luma_pattern(&gradient(0, false));
+        let flat = [0.0; 16];
+        assert!(pattern_distance(&rising, &falling) > pattern_distance(&rising, &flat));
+        assert_eq!(pattern_distance(&rising, &falling), pattern_distance(&falling, &rising));
+    }
+}
@@ -0,0 +1,147 @@
+//! In-memory stand-ins for the catalog store and image files, for unit tests.
+
+use crate::app::traits::{CatalogStore, ImageIo};
+use crate::domain::{
+    Catalog, ColorSpace, ExifInfo, MatchMetric, MosaicSpec, OutputOptions, RasterLayout, Tile,
+    TileMetadata, TilesSource,
+};
+use crate::error::AppResult;
+use image::{DynamicImage, ImageFormat, RgbImage, RgbaImage};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A catalog held in memory; `save` replaces it.
+pub(crate) struct MemoryCatalog {
+    catalog: RefCell<Catalog>,
+}
+
+impl MemoryCatalog {
+    pub(crate) fn new(tiles: Vec<Tile>) -> Self {
+        MemoryCatalog {
+            catalog: RefCell::new(Catalog { root: None, tiles }),
+        }
+    }
+}
+
+impl CatalogStore for MemoryCatalog {
+    fn load(&self) -> AppResult<Catalog> {
+        Ok(self.catalog.borrow().clone())
+    }
+
+    fn save(&self, catalog: &Catalog) -> AppResult<()> {
+        *self.catalog.borrow_mut() = catalog.clone();
+        Ok(())
+    }
+
+    fn thumbnail_dir(&self) -> PathBuf {
+        PathBuf::from("thumbnails")
+    }
+}
+
+pub(crate) fn tile(id: &str, path: &str, avg_color: [u8; 3]) -> Tile {
+    Tile {
+        id: id.to_string(),
+        path: PathBuf::from(path),
+        avg_color,
+        thumbnail: None,
+        phash: None,
+        tags: Vec::new(),
+        metadata: TileMetadata::default(),
+    }
+}
+
+/// A plain run over the whole catalog, with every optional feature off.
+pub(crate) fn mosaic_spec(input: &str, output: &str, tile_size: u32) -> MosaicSpec {
+    MosaicSpec {
+        input: PathBuf::from(input),
+        output: PathBuf::from(output),
+        tile_size,
+        tiles_source: TilesSource::Catalog,
+        color_space: ColorSpace::Srgb,
+        metric: MatchMetric::Rgb,
+        matte: [255, 255, 255],
+        preserve_transparency: false,
+        memory_limit: None,
+        output_options: OutputOptions::default(),
+        mask: None,
+        detail: None,
+        structure_weight: None,
+        dither: None,
+        sampling: None,
+        pins: Vec::new(),
+        bans: Vec::new(),
+        manifest: None,
+        heatmap: None,
+    }
+}
+
+/// Image files by path. Written images can be read back; a `.dzi` path
+/// stands for a Deep Zoom pyramid.
+#[derive(Default)]
+pub(crate) struct MemoryImages {
+    images: RefCell<HashMap<PathBuf, DynamicImage>>,
+}
+
+impl MemoryImages {
+    pub(crate) fn with(self, path: &str, image: RgbImage) -> Self {
+        let image = DynamicImage::ImageRgb8(image);
+        self.images.borrow_mut().insert(PathBuf::from(path), image);
+        self
+    }
+
+    pub(crate) fn get(&self, path: &str) -> Option<DynamicImage> {
+        self.images.borrow().get(Path::new(path)).cloned()
+    }
+}
+
+impl ImageIo for MemoryImages {
+    fn read(&self, path: &Path) -> AppResult<DynamicImage> {
+        let image = self.images.borrow().get(path).cloned();
+        image.ok_or_else(|| io::Error::from(io::ErrorKind::NotFound).into())
+    }
+
+    fn detect_format(&self, path: &Path) -> Option<ImageFormat> {
+        self.images.borrow().contains_key(path).then_some(ImageFormat::Png)
+    }
+
+    fn read_exif(&self, _path: &Path) -> AppResult<ExifInfo> {
+        Ok(ExifInfo::default())
+    }
+
+    fn write_rgb(&self, path: &Path, image: &RgbImage, _options: &OutputOptions) -> AppResult<()> {
+        let image = DynamicImage::ImageRgb8(image.clone());
+        self.images.borrow_mut().insert(path.to_path_buf(), image);
+        Ok(())
+    }
+
+    fn can_stream(&self, _path: &Path, _options: &OutputOptions, _alpha: bool) -> AppResult<bool> {
+        Ok(false)
+    }
+
+    fn is_pyramid(&self, path: &Path) -> bool {
+        path.extension().is_some_and(|ext| ext == "dzi")
+    }
+
+    fn write_bands(
+        &self,
+        path: &Path,
+        layout: &RasterLayout,
+        bands: &mut dyn Iterator<Item = AppResult<Vec<u8>>>,
+        _options: &OutputOptions,
+    ) -> AppResult<()> {
+        let mut samples = Vec::new();
+        for band in bands {
+            samples.extend(band?);
+        }
+        let (width, height) = (layout.width, layout.height);
+        let image = if layout.alpha {
+            DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, samples).unwrap())
+        } else {
+            DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, samples).unwrap())
+        };
+        self.images.borrow_mut().insert(path.to_path_buf(), image);
+        Ok(())
+    }
+}
@@ -1,14 +1,39 @@
-use crate::domain::Catalog;
+use crate::domain::{Catalog, ExifInfo, OutputOptions, RasterLayout};
 use crate::error::AppResult;
-use image::DynamicImage;
-use std::path::Path;
+use image::{DynamicImage, ImageFormat};
+use std::path::{Path, PathBuf};
 
 pub trait CatalogStore {
     fn load(&self) -> AppResult<Catalog>;
     fn save(&self, catalog: &Catalog) -> AppResult<()>;
+    fn thumbnail_dir(&self) -> PathBuf;
 }
 
 pub trait ImageIo {
     fn read(&self, path: &Path) -> AppResult<DynamicImage>;
-    fn write_rgb(&self, path: &Path, image: &image::RgbImage) -> AppResult<()>;
+    /// Returns the format of a decodable image file, judged by content.
+    fn detect_format(&self, path: &Path) -> Option<ImageFormat>;
+    fn read_exif(&self, path: &Path) -> AppResult<ExifInfo>;
+    fn write_rgb(
+        &self,
+        path: &Path,
+        image: &image::RgbImage,
+        options: &OutputOptions,
+    ) -> AppResult<()>;
+    /// Whether `write_bands` encodes this output incrementally rather than
+    /// buffering it whole. Fails for outputs that cannot be written at all,
+    /// including ones that cannot hold the transparency `alpha` asks for.
+    fn can_stream(&self, path: &Path, options: &OutputOptions, alpha: bool) -> AppResult<bool>;
+    /// Whether `path` is written as a Deep Zoom pyramid of many files rather
+    /// than as a single image.
+    fn is_pyramid(&self, path: &Path) -> bool;
+    /// Writes an image delivered top to bottom as bands of whole rows of
+    /// interleaved samples. Every band but the last has the same height.
+    fn write_bands(
+        &self,
+        path: &Path,
+        layout: &RasterLayout,
+        bands: &mut dyn Iterator<Item = AppResult<Vec<u8>>>,
+        options: &OutputOptions,
+    ) -> AppResult<()>;
 }
@@ -1,6 +1,16 @@
-use crate::domain::{Catalog, MosaicResult, Tile};
-use clap::{Args, Parser, Subcommand};
-use std::path::PathBuf;
+use crate::app::image_utils::{hex_color, parse_hex_color};
+use crate::domain::{
+    Catalog, CatalogBundle, CatalogStats, CompareResult, DetailSource, DitherKernel, GapReport,
+    HashKind, ImportReport, MaskRegion, MatchMetric, MosaicResult, PngFilter, SearchHit,
+    SymlinkPolicy, Tile, TileBan, TilePin, TilesSource, Variation,
+};
+use crate::error::AppResult;
+use crate::infra::image_io::supported_formats;
+use crate::infra::manifest::csv_field;
+use chrono::{DateTime, NaiveDate, NaiveTime};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use image::ImageFormat;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[command(name = "andreamosaic", version, about = "Andrea Mosaic CLI")]
@@ -21,37 +31,410 @@ pub enum Commands {
         #[command(subcommand)]
         command: CatalogCommands,
     },
+    /// Build a mosaic of an input image from catalog or directory tiles.
     Generate(GenerateArgs),
+    /// Re-render a mosaic from a placement manifest without matching again.
+    Render(RenderArgs),
+    /// Generate one mosaic per combination of settings and lay them out on a
+    /// labelled contact sheet at --output.
+    Compare(CompareArgs),
 }
 
 #[derive(Subcommand)]
 pub enum CatalogCommands {
-    Add { path: PathBuf },
+    Add {
+        path: PathBuf,
+        /// Perceptual hash stored on each tile (ahash, dhash or phash).
+        #[arg(long, default_value_t = HashKind::Difference)]
+        hash: HashKind,
+        /// Skip images that are near-duplicates of tiles already in the catalog.
+        #[arg(long)]
+        skip_near_duplicates: bool,
+        #[arg(long, default_value_t = 8)]
+        duplicate_threshold: u32,
+        /// Tag added to every imported tile (repeatable).
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Average tile colors in linear light instead of on sRGB values.
+        #[arg(long)]
+        linear: bool,
+        #[command(flatten)]
+        scan: ScanArgs,
+        #[command(flatten)]
+        quality: QualityArgs,
+    },
     List,
     Remove { id: String },
+    /// Add tags to a tile, or remove them with --remove.
+    Tag {
+        id: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Find tiles by color, path and metadata.
+    Search(SearchArgs),
+    /// List clusters of near-duplicate tiles.
+    Dedupe {
+        #[arg(long, default_value_t = HashKind::Difference)]
+        hash: HashKind,
+        /// Maximum Hamming distance between hashes in a cluster.
+        #[arg(long, default_value_t = 8)]
+        threshold: u32,
+    },
+    /// Show a color histogram and coverage of the catalog.
+    Stats {
+        /// Histogram bins per color channel.
+        #[arg(long, default_value_t = 4)]
+        levels: u8,
+    },
+    /// Report which colors of a target image the catalog serves poorly.
+    Gaps {
+        #[arg(long)]
+        input: PathBuf,
+        #[arg(long)]
+        tile_size: Option<u32>,
+        /// RGB distance above which a cell counts as poorly served.
+        #[arg(long, default_value_t = 30.0)]
+        max_error: f32,
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        /// Write a swatch image of target vs. closest available colors.
+        #[arg(long)]
+        swatches: Option<PathBuf>,
+        /// Average input cells in linear light.
+        #[arg(long)]
+        linear: bool,
+        /// Distance tiles are ranked by, as for generate.
+        #[arg(long, default_value_t = MatchMetric::Rgb)]
+        metric: MatchMetric,
+        /// Background for transparent input areas (#rrggbb, default black).
+        #[arg(long, value_parser = parse_hex_color)]
+        matte: Option<[u8; 3]>,
+        /// Leave out cells the input keeps fully transparent.
+        #[arg(long)]
+        transparent: bool,
+    },
+    /// Show or set the library root that tile paths are stored relative to.
+    Root { path: Option<PathBuf> },
+    /// Move the library from one root to another.
+    Relocate { old_root: PathBuf, new_root: PathBuf },
+    /// Bundle catalog metadata into a single archive.
+    Export {
+        archive: PathBuf,
+        #[arg(long)]
+        thumbnails: bool,
+        #[arg(long, default_value_t = 256)]
+        thumbnail_size: u32,
+    },
+    /// Merge tiles from an exported archive into the catalog.
+    Import {
+        archive: PathBuf,
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+pub struct ScanArgs {
+    /// Only import files matching this glob (repeatable).
+    #[arg(long)]
+    pub include: Vec<String>,
+    /// Skip files and directories matching this glob (repeatable).
+    #[arg(long)]
+    pub exclude: Vec<String>,
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+    /// Also import hidden files and directories.
+    #[arg(long)]
+    pub hidden: bool,
+    /// Whether to follow or skip symbolic links (follow, skip).
+    #[arg(long, default_value = "skip")]
+    pub symlinks: SymlinkPolicy,
+}
+
+#[derive(Args)]
+pub struct QualityArgs {
+    #[arg(long)]
+    pub min_width: Option<u32>,
+    #[arg(long)]
+    pub min_height: Option<u32>,
+    /// Minimum variance of the Laplacian; lower means blurrier.
+    #[arg(long)]
+    pub min_sharpness: Option<f64>,
+    /// Maximum fraction (0-1) of near-black pixels.
+    #[arg(long)]
+    pub max_underexposed: Option<f64>,
+    /// Maximum fraction (0-1) of near-white pixels.
+    #[arg(long)]
+    pub max_overexposed: Option<f64>,
+}
+
+#[derive(Args)]
+pub struct SearchArgs {
+    /// Rank tiles by closeness to this color (#rrggbb).
+    #[arg(long, value_parser = parse_hex_color)]
+    pub color: Option<[u8; 3]>,
+    /// Path substring, or glob when it contains *, ? or [.
+    #[arg(long)]
+    pub path: Option<String>,
+    /// Required tag (repeatable).
+    #[arg(long = "tag")]
+    pub tags: Vec<String>,
+    #[arg(long)]
+    pub min_width: Option<u32>,
+    #[arg(long)]
+    pub max_width: Option<u32>,
+    #[arg(long)]
+    pub min_height: Option<u32>,
+    #[arg(long)]
+    pub max_height: Option<u32>,
+    /// Imported on or after this date (YYYY-MM-DD).
+    #[arg(long, value_parser = parse_date)]
+    pub imported_after: Option<i64>,
+    /// Imported before this date (YYYY-MM-DD).
+    #[arg(long, value_parser = parse_date)]
+    pub imported_before: Option<i64>,
+    #[arg(long)]
+    pub limit: Option<usize>,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
 }
 
 #[derive(Args)]
 pub struct GenerateArgs {
     #[arg(long)]
     pub input: Option<PathBuf>,
+    /// Output image; a .dzi path writes a deep-zoom tile pyramid plus an HTML viewer.
     #[arg(long)]
     pub output: Option<PathBuf>,
     #[arg(long)]
     pub tiles: Option<String>,
     #[arg(long)]
     pub tile_size: Option<u32>,
+    /// Average and resample in linear light instead of on sRGB values.
+    #[arg(long)]
+    pub linear: bool,
+    /// Distance tiles are ranked by: rgb, or lab for perceptual ΔE (default rgb).
+    #[arg(long)]
+    pub metric: Option<MatchMetric>,
+    /// Background for transparent tiles and input areas (#rrggbb, default black).
+    #[arg(long, value_parser = parse_hex_color)]
+    pub matte: Option<[u8; 3]>,
+    /// Keep transparent areas of the input transparent (RGBA output).
+    #[arg(long)]
+    pub transparent: bool,
+    /// Memory budget for rendering, e.g. 512M or 2G; the output is written in
+    /// row bands sized to fit.
+    #[arg(long, value_parser = parse_byte_size)]
+    pub memory_limit: Option<u64>,
+    /// Image whose colors pick the tiles each area is drawn from; see --region.
+    #[arg(long)]
+    pub mask: Option<PathBuf>,
+    /// Tiles for one mask color, as #rrggbb=catalog, #rrggbb=tag:TAG[,TAG...]
+    /// (catalog tiles with every tag) or #rrggbb=DIR (repeatable). Other colors
+    /// use --tiles.
+    #[arg(long = "region", value_parser = parse_mask_region)]
+    pub regions: Vec<MaskRegion>,
+    /// Draw a tile over a cell or region before matching the rest, as
+    /// TILE_ID@ROW,COL or TILE_ID@ROW,COL:ROW,COL (zero-based, repeatable).
+    #[arg(long = "pin")]
+    pub pins: Vec<TilePin>,
+    /// Never place a tile, anywhere (TILE_ID) or within a region
+    /// (TILE_ID@ROW,COL[:ROW,COL]); repeatable.
+    #[arg(long = "ban")]
+    pub bans: Vec<TileBan>,
+    /// Split cells into smaller tiles where detail matters: `auto` for the
+    /// input's local contrast, or a grayscale image where white is most important.
+    #[arg(long)]
+    pub detail: Option<DetailSource>,
+    /// Number of tile sizes with --detail, each half the previous (default 3).
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=8))]
+    pub detail_levels: Option<u32>,
+    /// Also split cells whose best match is further than this RGB distance,
+    /// scaled down towards zero as importance rises.
+    #[arg(long)]
+    pub detail_tolerance: Option<f32>,
+    /// Also match each cell's pattern of light and dark areas, so tiles line
+    /// up with edges; 1.0 weighs it like the color match.
+    #[arg(long)]
+    pub structure_weight: Option<f32>,
+    /// Pass each cell's color error on to later cells (floyd-steinberg or
+    /// atkinson), trading banding for grain with small catalogs.
+    #[arg(long)]
+    pub dither: Option<DitherKernel>,
+    /// Dither every other row right to left.
+    #[arg(long)]
+    pub serpentine: bool,
+    /// Pick each tile at random from the K closest.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub top_k: Option<u32>,
+    /// Pick each tile at random from those within this RGB distance of the closest.
+    #[arg(long)]
+    pub pick_tolerance: Option<f32>,
+    /// Seed for --top-k and --pick-tolerance (default: random, and printed).
+    #[arg(long)]
+    pub seed: Option<u64>,
+    #[command(flatten)]
+    pub encoding: EncodingArgs,
+    /// Save which tile was placed in each cell, as .json or .csv.
+    #[arg(long)]
+    pub manifest: Option<PathBuf>,
+    /// Save an image of each cell's color error, black (none) to white.
+    #[arg(long)]
+    pub heatmap: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct CompareArgs {
+    /// A setting and the values to try, e.g. tile_size=16,32,64 or metric=rgb,lab
+    /// (repeatable). Settings: tile_size, metric, linear, dither,
+    /// structure_weight and detail.
+    #[arg(long = "vary", required = true)]
+    pub variations: Vec<Variation>,
+    #[command(flatten)]
+    pub generate: GenerateArgs,
+}
+
+#[derive(Args)]
+pub struct RenderArgs {
+    /// JSON manifest written by `generate --manifest`.
+    #[arg(long)]
+    pub manifest: PathBuf,
+    #[arg(long)]
+    pub output: PathBuf,
+    /// Cell size in the output (default: the manifest's tile size).
+    #[arg(long)]
+    pub tile_size: Option<u32>,
+    /// Width in pixels of the lines between and around tiles.
+    #[arg(long, default_value_t = 0)]
+    pub grout: u32,
+    /// Grout color (#rrggbb).
+    #[arg(long, value_parser = parse_hex_color, default_value = "#ffffff")]
+    pub grout_color: [u8; 3],
+    #[command(flatten)]
+    pub encoding: EncodingArgs,
 }
 
-pub fn print_catalog_add(added: &[Tile]) {
-    if added.is_empty() {
+/// Encoder settings shared by commands that write images.
+#[derive(Args)]
+pub struct EncodingArgs {
+    /// Output format by extension, e.g. png, jpg, webp or tiff; defaults to the
+    /// output extension.
+    #[arg(long, value_parser = parse_image_format)]
+    pub format: Option<ImageFormat>,
+    /// JPEG quality, 1 to 100 (default 90).
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub jpeg_quality: Option<u8>,
+    /// PNG compression level, 0 (none) to 9 (smallest, slowest).
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=9))]
+    pub png_compression: Option<u8>,
+    /// PNG scanline filter (none, sub, up, avg, paeth or adaptive).
+    #[arg(long)]
+    pub png_filter: Option<PngFilter>,
+    /// Print resolution stored in PNG and JPEG output; other formats cannot store it.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=65535))]
+    pub dpi: Option<u32>,
+}
+
+pub fn print_catalog_add(report: &ImportReport) {
+    if report.added.is_empty() {
         println!("No new tiles added.");
+    } else {
+        println!("Added {} tile(s):", report.added.len());
+        for tile in &report.added {
+            println!("{}  {}", tile.id, tile.path.display());
+        }
+    }
+
+    if !report.skipped.is_empty() {
+        println!("Rejected {} image(s):", report.skipped.len());
+        for skipped in &report.skipped {
+            println!("{}  {}", skipped.path.display(), skipped.reason);
+        }
+    }
+}
+
+pub fn print_catalog_tag(tile: &Tile) {
+    if tile.tags.is_empty() {
+        println!("Tile {} has no tags.", tile.id);
+    } else {
+        println!("Tile {} tags: {}", tile.id, tile.tags.join(", "));
+    }
+}
+
+pub fn print_search_results(hits: &[SearchHit], format: OutputFormat) -> AppResult<()> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(hits)?),
+        OutputFormat::Csv => {
+            println!("id,path,color,width,height,imported_at,tags,distance");
+            for hit in hits {
+                let tile = &hit.tile;
+                println!(
+                    "{},{},{},{},{},{},{},{}",
+                    tile.id,
+                    csv_field(&tile.path.to_string_lossy()),
+                    hex_color(tile.avg_color),
+                    optional(tile.metadata.width),
+                    optional(tile.metadata.height),
+                    tile.metadata.imported_at.map(format_date).unwrap_or_default(),
+                    csv_field(&tile.tags.join(";")),
+                    hit.distance.map(|d| format!("{d:.1}")).unwrap_or_default()
+                );
+            }
+        }
+        OutputFormat::Table => {
+            if hits.is_empty() {
+                println!("No matching tiles.");
+                return Ok(());
+            }
+
+            println!("Matching tiles ({}):", hits.len());
+            for hit in hits {
+                let tile = &hit.tile;
+                let size = match (tile.metadata.width, tile.metadata.height) {
+                    (Some(width), Some(height)) => format!("{width}x{height}"),
+                    _ => "-".to_string(),
+                };
+                let distance = hit.distance.map(|d| format!("{d:>6.1}")).unwrap_or_default();
+                println!(
+                    "{} {} {:<16} {:>11} {:>10} {}  {}  {}",
+                    swatch(tile.avg_color),
+                    hex_color(tile.avg_color),
+                    &tile.id[..tile.id.len().min(16)],
+                    size,
+                    tile.metadata.imported_at.map(format_date).unwrap_or_else(|| "-".to_string()),
+                    distance,
+                    tile.path.display(),
+                    tile.tags.join(", ")
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn print_catalog_dedupe(clusters: &[Vec<Tile>]) {
+    if clusters.is_empty() {
+        println!("No near-duplicates found.");
         return;
     }
 
-    println!("Added {} tile(s):", added.len());
-    for tile in added {
-        println!("{}  {}", tile.id, tile.path.display());
+    println!("Near-duplicate clusters ({}):", clusters.len());
+    for (index, cluster) in clusters.iter().enumerate() {
+        println!();
+        println!("Cluster {} ({} tiles):", index + 1, cluster.len());
+        for tile in cluster {
+            println!("{}  {}", tile.id, tile.path.display());
+        }
     }
 }
 
@@ -71,8 +454,312 @@ pub fn print_catalog_remove(removed: &Tile) {
     println!("Removed tile {} ({})", removed.id, removed.path.display());
 }
 
+pub fn print_catalog_stats(stats: &CatalogStats) {
+    if stats.tile_count == 0 {
+        println!("Catalog is empty.");
+        return;
+    }
+
+    println!("Tiles: {}", stats.tile_count);
+    println!(
+        "Mean color: {} {}",
+        swatch(stats.mean_color),
+        hex_color(stats.mean_color)
+    );
+    if let Some(brightness) = stats.mean_brightness {
+        println!("Mean brightness: {:.2}", brightness);
+    }
+    if let Some(saturation) = stats.mean_saturation {
+        println!("Mean saturation: {:.2}", saturation);
+    }
+    let spaces: Vec<String> = stats
+        .color_spaces
+        .iter()
+        .map(|(space, count)| format!("{space} {count}"))
+        .collect();
+    println!("Averaged in: {}", spaces.join(", "));
+    println!(
+        "Coverage: {}/{} bins ({:.1}%, {} levels per channel)",
+        stats.bins.len(),
+        stats.total_bins,
+        stats.coverage() * 100.0,
+        stats.levels
+    );
+    println!();
+
+    let max_count = stats.bins.first().map(|bin| bin.count).unwrap_or(1);
+    for bin in &stats.bins {
+        let bar = "#".repeat((bin.count * 40).div_ceil(max_count));
+        println!(
+            "{} {}  {:>6}  {}",
+            swatch(bin.color),
+            hex_color(bin.color),
+            bin.count,
+            bar
+        );
+    }
+}
+
+pub fn print_catalog_gaps(report: &GapReport) {
+    println!(
+        "Poorly served cells: {}/{} ({:.1}%)",
+        report.poorly_served,
+        report.total_cells,
+        report.poorly_served as f32 * 100.0 / report.total_cells.max(1) as f32
+    );
+    println!("Mean matching error: {:.1}", report.mean_error);
+
+    if report.gaps.is_empty() {
+        println!("No color gaps found.");
+        return;
+    }
+
+    println!();
+    println!("Target            Closest tile      Cells  Error");
+    for gap in &report.gaps {
+        println!(
+            "{} {}  {} {}  {:>5}  {:>5.1}",
+            swatch(gap.target),
+            hex_color(gap.target),
+            swatch(gap.nearest),
+            hex_color(gap.nearest),
+            gap.cells,
+            gap.mean_error
+        );
+    }
+
+    if let Some(path) = &report.swatches {
+        println!();
+        println!("Swatches written to {}", path.display());
+    }
+}
+
+pub fn print_catalog_root(catalog: &Catalog) {
+    match &catalog.root {
+        Some(root) => println!("Library root: {}", root.display()),
+        None => println!("No library root set; tile paths are absolute."),
+    }
+}
+
+pub fn print_catalog_set_root(root: &Path, relative: usize) {
+    println!("Library root set to {}", root.display());
+    println!("Tiles stored relative to root: {relative}");
+}
+
+pub fn print_catalog_relocate(moved: usize) {
+    println!("Relocated {moved} tile(s).");
+}
+
+pub fn print_catalog_export(archive: &Path, bundle: &CatalogBundle) {
+    println!(
+        "Exported {} tile(s) and {} thumbnail(s) to {}",
+        bundle.catalog.tiles.len(),
+        bundle.thumbnails.len(),
+        archive.display()
+    );
+}
+
+pub fn print_catalog_import(added: &[Tile]) {
+    if added.is_empty() {
+        println!("No new tiles imported.");
+        return;
+    }
+
+    println!("Imported {} tile(s):", added.len());
+    for tile in added {
+        println!("{}  {}", tile.id, tile.path.display());
+    }
+}
+
 pub fn print_generate_result(result: &MosaicResult) {
     println!("Mosaic generated at {}", result.output.display());
     println!("Grid: {} x {}", result.grid_width, result.grid_height);
     println!("Tiles used: {}", result.tiles_used);
+    if result.bands > 1 {
+        println!("Rendered in {} bands", result.bands);
+    }
+    if let Some(quality) = &result.quality {
+        println!(
+            "Quality: PSNR {:.2} dB, SSIM {:.3}, mean \u{394}E {:.2}",
+            quality.psnr, quality.ssim, quality.mean_delta_e
+        );
+    }
+    if let Some(seed) = result.seed {
+        println!("Seed: {seed}");
+    }
+    if let Some(manifest) = &result.manifest {
+        println!("Placements recorded: {}", manifest.placements.len());
+    }
+}
+
+pub fn print_compare_result(result: &CompareResult) {
+    let width = result
+        .runs
+        .iter()
+        .map(|run| run.label.len())
+        .max()
+        .unwrap_or(0)
+        .max("Settings".len());
+    println!(
+        "{:<width$}  {:>9}  {:>7}  {:>6}  {:>6}  {:>8}",
+        "Settings", "Grid", "PSNR", "SSIM", "\u{394}E", "Time"
+    );
+    for run in &result.runs {
+        let result = &run.result;
+        let grid = format!("{}x{}", result.grid_width, result.grid_height);
+        let (psnr, ssim, delta_e) = match &result.quality {
+            Some(quality) => (
+                format!("{:.2}", quality.psnr),
+                format!("{:.3}", quality.ssim),
+                format!("{:.2}", quality.mean_delta_e),
+            ),
+            None => ("-".to_string(), "-".to_string(), "-".to_string()),
+        };
+        println!(
+            "{:<width$}  {:>9}  {:>7}  {:>6}  {:>6}  {:>7.2}s",
+            run.label,
+            grid,
+            psnr,
+            ssim,
+            delta_e,
+            run.elapsed.as_secs_f64()
+        );
+    }
+    println!("Contact sheet written to {}", result.sheet.display());
+}
+
+fn swatch(color: [u8; 3]) -> String {
+    format!("\x1b[48;2;{};{};{}m    \x1b[0m", color[0], color[1], color[2])
+}
+
+/// Parses an output format by extension; the error lists every format this
+/// build can write.
+pub fn parse_image_format(value: &str) -> Result<ImageFormat, String> {
+    ImageFormat::from_extension(value.trim_start_matches('.'))
+        .filter(|format| format.writing_enabled())
+        .ok_or_else(|| {
+            let writable: Vec<&str> = supported_formats()
+                .into_iter()
+                .filter(|format| format.writing_enabled())
+                .filter_map(|format| format.extensions_str().first().copied())
+                .collect();
+            format!("unsupported output format: {value} (expected {})", writable.join(", "))
+        })
+}
+
+/// Parses `#rrggbb=SOURCE`, where SOURCE is `catalog`, `tag:TAG[,TAG...]` or a
+/// tile directory.
+pub fn parse_mask_region(value: &str) -> Result<MaskRegion, String> {
+    let invalid = || format!("invalid region: {value} (expected #rrggbb=SOURCE)");
+    let (color, source) = value.split_once('=').ok_or_else(invalid)?;
+    let color = parse_hex_color(color).map_err(|_| invalid())?;
+    let source = source.trim();
+    if source.is_empty() {
+        return Err(invalid());
+    }
+
+    if let Some(tags) = source.strip_prefix("tag:") {
+        let tags: Vec<String> = tags
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect();
+        if tags.is_empty() {
+            return Err(format!("invalid region: {value} (tag: needs at least one tag)"));
+        }
+        return Ok(MaskRegion {
+            color,
+            source: TilesSource::Catalog,
+            tags,
+        });
+    }
+
+    let source = if source.eq_ignore_ascii_case("catalog") {
+        TilesSource::Catalog
+    } else {
+        TilesSource::Directory(PathBuf::from(source))
+    };
+    Ok(MaskRegion {
+        color,
+        source,
+        tags: Vec::new(),
+    })
+}
+
+/// Parses a byte count with an optional binary K, M, G or T suffix.
+pub fn parse_byte_size(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let upper = trimmed.to_ascii_uppercase();
+    let number = upper.trim_end_matches("IB").trim_end_matches('B');
+    let (digits, shift) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 10),
+        Some('M') => (&number[..number.len() - 1], 20),
+        Some('G') => (&number[..number.len() - 1], 30),
+        Some('T') => (&number[..number.len() - 1], 40),
+        _ => (number, 0),
+    };
+    let amount: f64 = digits
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid size: {trimmed} (expected e.g. 512M or 2G)"))?;
+    if amount <= 0.0 {
+        return Err(format!("size must be positive: {trimmed}"));
+    }
+    Ok((amount * (1u64 << shift) as f64) as u64)
+}
+
+fn parse_date(value: &str) -> Result<i64, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_time(NaiveTime::MIN).and_utc().timestamp())
+        .map_err(|_| format!("invalid date: {value} (expected YYYY-MM-DD)"))
+}
+
+fn format_date(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|date| date.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+fn optional(value: Option<u32>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_sizes_accept_binary_suffixes() {
+        assert_eq!(parse_byte_size("512"), Ok(512));
+        assert_eq!(parse_byte_size("64k"), Ok(64 << 10));
+        assert_eq!(parse_byte_size("512M"), Ok(512 << 20));
+        assert_eq!(parse_byte_size("512MiB"), Ok(512 << 20));
+        assert_eq!(parse_byte_size(" 2GB "), Ok(2 << 30));
+        assert_eq!(parse_byte_size("1.5G"), Ok(3 << 29));
+        assert_eq!(parse_byte_size("1T"), Ok(1 << 40));
+    }
+
+    #[test]
+    fn byte_sizes_reject_nonsense() {
+        assert!(parse_byte_size("").is_err());
+        assert!(parse_byte_size("M").is_err());
+        assert!(parse_byte_size("lots").is_err());
+        assert!(parse_byte_size("0").is_err());
+        assert!(parse_byte_size("-1G").is_err());
+    }
+
+    #[test]
+    fn output_formats_are_named_by_extension() {
+        assert_eq!(parse_image_format("png"), Ok(ImageFormat::Png));
+        assert_eq!(parse_image_format(".JPG"), Ok(ImageFormat::Jpeg));
+        assert_eq!(parse_image_format("jpeg"), Ok(ImageFormat::Jpeg));
+    }
+
+    #[test]
+    fn unknown_output_formats_list_the_writable_ones() {
+        let error = parse_image_format("psd").unwrap_err();
+        assert!(error.starts_with("unsupported output format: psd (expected "), "{error}");
+        assert!(error.contains("png") && error.contains("jpg"), "{error}");
+    }
 }
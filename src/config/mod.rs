@@ -17,6 +17,31 @@ pub struct GenerateConfig {
     pub output: Option<PathBuf>,
     pub tiles: Option<String>,
     pub tile_size: Option<u32>,
+    pub linear: Option<bool>,
+    pub metric: Option<String>,
+    pub matte: Option<String>,
+    pub transparent: Option<bool>,
+    pub memory_limit: Option<String>,
+    pub format: Option<String>,
+    pub jpeg_quality: Option<u8>,
+    pub png_compression: Option<u8>,
+    pub png_filter: Option<String>,
+    pub dpi: Option<u32>,
+    pub mask: Option<PathBuf>,
+    pub regions: Option<Vec<String>>,
+    pub pins: Option<Vec<String>>,
+    pub bans: Option<Vec<String>>,
+    pub detail: Option<String>,
+    pub detail_levels: Option<u32>,
+    pub detail_tolerance: Option<f32>,
+    pub structure_weight: Option<f32>,
+    pub dither: Option<String>,
+    pub serpentine: Option<bool>,
+    pub top_k: Option<u32>,
+    pub pick_tolerance: Option<f32>,
+    pub seed: Option<u64>,
+    pub manifest: Option<PathBuf>,
+    pub heatmap: Option<PathBuf>,
 }
 
 pub fn load(path: Option<&Path>) -> AppResult<FileConfig> {
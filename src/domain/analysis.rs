@@ -0,0 +1,69 @@
+use crate::domain::{ColorSpace, MatchMetric};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct ColorBin {
+    /// Center of the bin in sRGB.
+    pub color: [u8; 3],
+    pub count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct CatalogStats {
+    pub tile_count: usize,
+    pub levels: u8,
+    pub mean_color: [u8; 3],
+    /// Averages over tiles that have the metadata recorded.
+    pub mean_brightness: Option<f64>,
+    pub mean_saturation: Option<f64>,
+    /// Tiles per color space their average was computed in.
+    pub color_spaces: Vec<(ColorSpace, usize)>,
+    /// Occupied bins, most populated first.
+    pub bins: Vec<ColorBin>,
+    pub total_bins: usize,
+}
+
+impl CatalogStats {
+    pub fn coverage(&self) -> f32 {
+        if self.total_bins == 0 {
+            return 0.0;
+        }
+        self.bins.len() as f32 / self.total_bins as f32
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GapSpec {
+    pub input: PathBuf,
+    pub tile_size: u32,
+    /// RGB distance above which a cell counts as poorly served.
+    pub max_error: f32,
+    pub limit: usize,
+    pub swatches: Option<PathBuf>,
+    pub color_space: ColorSpace,
+    pub metric: MatchMetric,
+    pub matte: [u8; 3],
+    /// Leave cells the input keeps fully transparent out of the report.
+    pub preserve_transparency: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ColorGap {
+    /// Mean target color of the cells in this bin.
+    pub target: [u8; 3],
+    /// Closest catalog color to `target`.
+    pub nearest: [u8; 3],
+    pub cells: usize,
+    pub mean_error: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct GapReport {
+    /// Cells a mosaic would place a tile in.
+    pub total_cells: usize,
+    pub poorly_served: usize,
+    pub mean_error: f32,
+    /// Worst-served target colors, most affected cells first.
+    pub gaps: Vec<ColorGap>,
+    pub swatches: Option<PathBuf>,
+}
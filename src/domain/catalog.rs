@@ -1,29 +1,347 @@
+use image::RgbImage;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tile {
     pub id: String,
     pub path: PathBuf,
     pub avg_color: [u8; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phash: Option<PerceptualHash>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub metadata: TileMetadata,
+}
+
+/// Facts captured when a tile is imported, so later commands need not reopen the file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TileMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// Unix timestamp (seconds) of the import.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imported_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+    /// Unix timestamp (seconds) of the file's last modification.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<i64>,
+    /// BLAKE3 hash of the file contents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// Mean luma, 0.0 to 1.0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<f64>,
+    /// Standard deviation of luma, 0.0 to 1.0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contrast: Option<f64>,
+    /// Mean HSV saturation, 0.0 to 1.0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub saturation: Option<f64>,
+    /// Mean per-channel variance around `avg_color`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_variance: Option<f64>,
+    /// Variance of the Laplacian of luma; low values indicate blur.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sharpness: Option<f64>,
+    /// Fraction of near-black pixels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub underexposed: Option<f64>,
+    /// Fraction of near-white pixels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub overexposed: Option<f64>,
+    /// Most common colors, most frequent first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dominant_colors: Vec<[u8; 3]>,
+    /// EXIF capture time as `YYYY-MM-DDTHH:MM:SS` (camera local time).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub captured_at: Option<String>,
+    /// EXIF orientation (1 to 8); already applied to `width`, `height` and thumbnails.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<u8>,
+    /// Space `avg_color` was averaged in; absent means sRGB.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_space: Option<ColorSpace>,
+}
+
+/// The subset of EXIF data the catalog cares about.
+#[derive(Debug, Clone, Default)]
+pub struct ExifInfo {
+    pub captured_at: Option<String>,
+    pub orientation: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashKind {
+    Average,
+    #[default]
+    Difference,
+    Perceptual,
+}
+
+/// A 64-bit perceptual hash, stored as `<kind>:<hex>` to stay within TOML integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PerceptualHash {
+    pub kind: HashKind,
+    pub value: u64,
+}
+
+/// How pixel values are combined when averaging and resampling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSpace {
+    /// Operate on the stored sRGB-encoded bytes.
+    #[default]
+    Srgb,
+    /// Decode to linear light first, then re-encode the result to sRGB.
+    Linear,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    pub hash_kind: HashKind,
+    pub color_space: ColorSpace,
+    /// Skip images within this Hamming distance of a tile already in the catalog.
+    pub near_duplicate_threshold: Option<u32>,
+    pub tags: Vec<String>,
+    pub scan: ScanOptions,
+    pub quality: QualityThresholds,
+}
+
+/// Import is refused for images failing any of the set thresholds.
+#[derive(Debug, Clone, Default)]
+pub struct QualityThresholds {
+    pub min_width: Option<u32>,
+    pub min_height: Option<u32>,
+    pub min_sharpness: Option<f64>,
+    /// Maximum fraction of near-black pixels.
+    pub max_underexposed: Option<f64>,
+    /// Maximum fraction of near-white pixels.
+    pub max_overexposed: Option<f64>,
+}
+
+/// Controls which files a directory walk picks up.
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Globs a file must match (any of), relative to the scanned directory.
+    pub include: Vec<String>,
+    /// Globs that exclude files and prune directories.
+    pub exclude: Vec<String>,
+    pub max_depth: Option<usize>,
+    pub include_hidden: bool,
+    pub symlinks: SymlinkPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    Follow,
+    #[default]
+    Skip,
+}
+
+#[derive(Debug, Clone)]
+pub struct SkippedImage {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    pub added: Vec<Tile>,
+    pub skipped: Vec<SkippedImage>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Catalog {
+    /// Library root that relative tile paths are resolved against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
+    #[serde(default)]
     pub tiles: Vec<Tile>,
 }
 
+/// Catalog metadata plus optional thumbnails, as stored in an export archive.
+#[derive(Debug, Clone, Default)]
+pub struct CatalogBundle {
+    pub catalog: Catalog,
+    pub thumbnails: Vec<(String, RgbImage)>,
+}
+
 impl Catalog {
+    pub fn contains(&self, id: &str, path: &Path) -> bool {
+        self.tiles.iter().any(|t| t.path == path || t.id == id)
+    }
+
     pub fn add_tile(&mut self, tile: Tile) -> bool {
-        if self.tiles.iter().any(|t| t.path == tile.path || t.id == tile.id) {
+        if self.contains(&tile.id, &tile.path) {
             return false;
         }
         self.tiles.push(tile);
         true
     }
 
+    pub fn find_by_id_mut(&mut self, id: &str) -> Option<&mut Tile> {
+        self.tiles.iter_mut().find(|t| t.id == id)
+    }
+
     pub fn remove_by_id(&mut self, id: &str) -> Option<Tile> {
         let index = self.tiles.iter().position(|t| t.id == id)?;
         Some(self.tiles.remove(index))
     }
+
+    /// Returns the on-disk location of a stored tile path.
+    pub fn resolve_path(&self, path: &Path) -> PathBuf {
+        match &self.root {
+            Some(root) if path.is_relative() => root.join(path),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    /// Returns the form a path is stored in: relative when it lives under the root.
+    pub fn storage_path(&self, path: &Path) -> PathBuf {
+        match &self.root {
+            Some(root) => path
+                .strip_prefix(root)
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|_| path.to_path_buf()),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// Sets the library root and rebases tile paths under it. Returns how many
+    /// tiles are now stored relative to the root.
+    pub fn set_root(&mut self, root: PathBuf) -> usize {
+        let resolved: Vec<PathBuf> = self
+            .tiles
+            .iter()
+            .map(|tile| self.resolve_path(&tile.path))
+            .collect();
+
+        self.root = Some(root);
+        for (tile, path) in self.tiles.iter_mut().zip(resolved) {
+            tile.path = path;
+        }
+
+        let mut relative = 0;
+        for index in 0..self.tiles.len() {
+            let stored = self.storage_path(&self.tiles[index].path);
+            if stored.is_relative() {
+                relative += 1;
+            }
+            self.tiles[index].path = stored;
+        }
+        relative
+    }
+
+    /// Moves the library from `old_root` to `new_root`. Relative tiles follow the
+    /// root; absolute tiles under `old_root` are rewritten. Returns the number of
+    /// tiles whose location changed.
+    pub fn relocate(&mut self, old_root: &Path, new_root: &Path) -> usize {
+        let mut moved = 0;
+        let root_moved = self.root.as_deref() == Some(old_root);
+        if root_moved {
+            self.root = Some(new_root.to_path_buf());
+        }
+
+        for tile in &mut self.tiles {
+            if tile.path.is_relative() {
+                if root_moved {
+                    moved += 1;
+                }
+                continue;
+            }
+            if let Ok(rest) = tile.path.strip_prefix(old_root) {
+                tile.path = new_root.join(rest);
+                moved += 1;
+            }
+        }
+        moved
+    }
+}
+
+impl HashKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HashKind::Average => "ahash",
+            HashKind::Difference => "dhash",
+            HashKind::Perceptual => "phash",
+        }
+    }
+}
+
+impl fmt::Display for HashKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for HashKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "ahash" | "average" => Ok(HashKind::Average),
+            "dhash" | "difference" => Ok(HashKind::Difference),
+            "phash" | "perceptual" => Ok(HashKind::Perceptual),
+            other => Err(format!("unknown hash kind: {other} (expected ahash, dhash or phash)")),
+        }
+    }
+}
+
+impl ColorSpace {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "srgb",
+            ColorSpace::Linear => "linear",
+        }
+    }
+}
+
+impl fmt::Display for ColorSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<PerceptualHash> for String {
+    fn from(hash: PerceptualHash) -> Self {
+        format!("{}:{:016x}", hash.kind, hash.value)
+    }
+}
+
+impl TryFrom<String> for PerceptualHash {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let (kind, hex) = value
+            .split_once(':')
+            .ok_or_else(|| format!("invalid perceptual hash: {value}"))?;
+        let value = u64::from_str_radix(hex, 16)
+            .map_err(|_| format!("invalid perceptual hash: {value}"))?;
+        Ok(PerceptualHash {
+            kind: kind.parse()?,
+            value,
+        })
+    }
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "follow" => Ok(SymlinkPolicy::Follow),
+            "skip" => Ok(SymlinkPolicy::Skip),
+            other => Err(format!("unknown symlink policy: {other} (expected follow or skip)")),
+        }
+    }
 }
@@ -0,0 +1,111 @@
+use crate::domain::{MosaicResult, MosaicSpec};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Renders a mosaic once per combination of varied settings and lays the
+/// results out on a contact sheet at `base.output`.
+#[derive(Debug, Clone)]
+pub struct CompareSpec {
+    /// Settings shared by every run; each run's output is named after the sheet.
+    pub base: MosaicSpec,
+    /// Combined in order, with the last varying fastest.
+    pub variations: Vec<Variation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variation {
+    pub setting: VariedSetting,
+    pub values: Vec<String>,
+}
+
+/// Generate settings `compare` can vary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariedSetting {
+    TileSize,
+    Metric,
+    /// `true` averages and resamples in linear light.
+    Linear,
+    /// A dither kernel, or `none`.
+    Dither,
+    StructureWeight,
+    /// `auto`, an importance map path, or `none`.
+    Detail,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompareResult {
+    pub sheet: PathBuf,
+    /// One per combination, in the order they appear on the sheet.
+    pub runs: Vec<CompareRun>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompareRun {
+    /// The run's settings, as `setting=value` pairs.
+    pub label: String,
+    pub result: MosaicResult,
+    pub elapsed: Duration,
+}
+
+impl VariedSetting {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VariedSetting::TileSize => "tile_size",
+            VariedSetting::Metric => "metric",
+            VariedSetting::Linear => "linear",
+            VariedSetting::Dither => "dither",
+            VariedSetting::StructureWeight => "structure_weight",
+            VariedSetting::Detail => "detail",
+        }
+    }
+}
+
+impl fmt::Display for VariedSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for VariedSetting {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().replace('-', "_").as_str() {
+            "tile_size" => Ok(VariedSetting::TileSize),
+            "metric" => Ok(VariedSetting::Metric),
+            "linear" => Ok(VariedSetting::Linear),
+            "dither" => Ok(VariedSetting::Dither),
+            "structure_weight" => Ok(VariedSetting::StructureWeight),
+            "detail" => Ok(VariedSetting::Detail),
+            other => Err(format!(
+                "cannot vary {other} (expected tile_size, metric, linear, dither, \
+                 structure_weight or detail)"
+            )),
+        }
+    }
+}
+
+/// Parses `SETTING=VALUE[,VALUE...]`.
+impl FromStr for Variation {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (setting, values) = value
+            .split_once('=')
+            .ok_or_else(|| format!("invalid variation: {value} (expected SETTING=VALUE,...)"))?;
+        let values: Vec<String> = values
+            .split(',')
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .collect();
+        if values.is_empty() {
+            return Err(format!("invalid variation: {value} (no values)"));
+        }
+        Ok(Variation {
+            setting: setting.trim().parse()?,
+            values,
+        })
+    }
+}
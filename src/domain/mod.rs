@@ -1,5 +1,20 @@
+pub mod analysis;
 pub mod catalog;
+pub mod compare;
 pub mod mosaic;
+pub mod search;
 
-pub use catalog::{Catalog, Tile};
-pub use mosaic::{MosaicResult, MosaicSpec, TilesSource};
+pub use analysis::{CatalogStats, ColorBin, ColorGap, GapReport, GapSpec};
+pub use catalog::{
+    Catalog, CatalogBundle, ColorSpace, ExifInfo, HashKind, ImportOptions, ImportReport,
+    PerceptualHash, QualityThresholds, ScanOptions, SkippedImage, SymlinkPolicy, Tile,
+    TileMetadata,
+};
+pub use compare::{CompareResult, CompareRun, CompareSpec, Variation, VariedSetting};
+pub use mosaic::{
+    CellRegion, DetailSource, DetailSpec, DitherKernel, DitherSpec, Manifest, MaskRegion,
+    MatchMetric, MosaicQuality, MosaicResult, MosaicSpec, OutputOptions, Placement, PngFilter,
+    RasterLayout, RegionMask, RenderSpec, SamplePool, SamplingSpec, TileBan, TilePin,
+    TileTransform, TilesSource,
+};
+pub use search::{SearchHit, SearchQuery};
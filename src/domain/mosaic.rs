@@ -1,6 +1,11 @@
+use crate::domain::ColorSpace;
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TilesSource {
     Catalog,
     Directory(PathBuf),
@@ -12,6 +17,174 @@ pub struct MosaicSpec {
     pub output: PathBuf,
     pub tile_size: u32,
     pub tiles_source: TilesSource,
+    /// Space cell and tile averages are compared in, and tiles are resampled in.
+    pub color_space: ColorSpace,
+    /// Distance used to rank tiles against a cell's color.
+    pub metric: MatchMetric,
+    /// Background that transparent tiles (and, unless preserved, the input) are composited over.
+    pub matte: [u8; 3],
+    /// Write RGBA output that keeps the input's transparent areas transparent.
+    pub preserve_transparency: bool,
+    /// Upper bound in bytes for decoded images and output bands.
+    pub memory_limit: Option<u64>,
+    pub output_options: OutputOptions,
+    /// Draws cells under each mask color from that color's own tiles.
+    pub mask: Option<RegionMask>,
+    /// Splits cells into smaller tiles where the image matters.
+    pub detail: Option<DetailSpec>,
+    /// Weight of the luma pattern match next to the color match; `None` matches
+    /// on color alone.
+    pub structure_weight: Option<f32>,
+    /// Carries each cell's color error over to its neighbours to smooth gradients.
+    pub dither: Option<DitherSpec>,
+    /// Picks randomly among close tiles instead of always the closest.
+    pub sampling: Option<SamplingSpec>,
+    /// Tiles drawn over fixed regions before the remaining cells are matched.
+    pub pins: Vec<TilePin>,
+    /// Tiles the matcher may not choose, everywhere or within a region.
+    pub bans: Vec<TileBan>,
+    /// Where to save the placement manifest; placements are only recorded when set.
+    pub manifest: Option<PathBuf>,
+    /// Where to save an image of each cell's color error.
+    pub heatmap: Option<PathBuf>,
+}
+
+/// Re-composites a saved manifest without matching again.
+#[derive(Debug, Clone)]
+pub struct RenderSpec {
+    pub manifest: Manifest,
+    pub output: PathBuf,
+    /// Output cell size; defaults to the manifest's tile size.
+    pub tile_size: Option<u32>,
+    /// Width in pixels of the lines drawn between and around tiles.
+    pub grout: u32,
+    pub grout_color: [u8; 3],
+    pub output_options: OutputOptions,
+}
+
+/// A mask image stretched over the input. Each cell belongs to the region whose
+/// color most of its mask pixels are close to; cells matching no region use
+/// `MosaicSpec::tiles_source`.
+#[derive(Debug, Clone)]
+pub struct RegionMask {
+    pub path: PathBuf,
+    pub regions: Vec<MaskRegion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaskRegion {
+    pub color: [u8; 3],
+    pub source: TilesSource,
+    /// Tags a catalog tile must all carry to be used in this region.
+    pub tags: Vec<String>,
+}
+
+/// Quarters cells, recursively, where an importance map says the image matters.
+#[derive(Debug, Clone)]
+pub struct DetailSpec {
+    pub source: DetailSource,
+    /// Number of tile sizes, each half the previous; the smallest is
+    /// `tile_size >> (levels - 1)`. A cell is split at level `l` (counted from
+    /// zero) once its importance reaches `(l + 1) / levels`.
+    pub levels: u32,
+    /// RGB error a match may have in a cell of no importance before the cell is
+    /// split anyway; the allowance shrinks to zero at full importance.
+    pub tolerance: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DetailSource {
+    /// Local luma contrast of the input, so edges, texture and text count.
+    Auto,
+    /// A grayscale image stretched over the input; white is most important.
+    Map(PathBuf),
+}
+
+/// How the distance between a cell's color and a tile's is measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMetric {
+    /// Euclidean distance between sRGB values.
+    #[default]
+    Rgb,
+    /// CIE76 ΔE, which follows perceived difference more closely.
+    Lab,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DitherSpec {
+    pub kernel: DitherKernel,
+    /// Visit every other row right to left, which avoids diagonal artifacts.
+    pub serpentine: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplingSpec {
+    pub pool: SamplePool,
+    /// Same seed, same spec and same tiles give the same mosaic.
+    pub seed: u64,
+}
+
+/// Which candidates a cell's tile is drawn from, all equally likely.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplePool {
+    /// The `k` closest.
+    Best(u32),
+    /// Those within this RGB distance of the closest.
+    Within(f32),
+}
+
+/// How a cell's color error is shared among the cells after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DitherKernel {
+    FloydSteinberg,
+    Atkinson,
+}
+
+/// Grid cells from (`top`, `left`) to (`bottom`, `right`) inclusive, counted from zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellRegion {
+    pub top: u32,
+    pub left: u32,
+    pub bottom: u32,
+    pub right: u32,
+}
+
+/// Draws one tile stretched across a region, in place of matching its cells.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TilePin {
+    pub tile_id: String,
+    pub region: CellRegion,
+}
+
+/// Keeps a tile out of a region, or out of the whole mosaic when `region` is unset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileBan {
+    pub tile_id: String,
+    pub region: Option<CellRegion>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    /// Encoder to use; inferred from the output extension when unset.
+    pub format: Option<ImageFormat>,
+    /// JPEG quality, 1 to 100.
+    pub jpeg_quality: Option<u8>,
+    /// PNG deflate level, 0 (store) to 9 (smallest).
+    pub png_compression: Option<u8>,
+    pub png_filter: Option<PngFilter>,
+    /// Print resolution embedded in PNG (pHYs) and JPEG (JFIF density) output.
+    pub dpi: Option<u32>,
+}
+
+/// Scanline filter applied before PNG compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    Adaptive,
 }
 
 #[derive(Debug, Clone)]
@@ -20,4 +193,378 @@ pub struct MosaicResult {
     pub tiles_used: usize,
     pub grid_width: u32,
     pub grid_height: u32,
+    /// Number of row bands the output was rendered in.
+    pub bands: u32,
+    /// Seed the tiles were sampled with, when sampling.
+    pub seed: Option<u64>,
+    /// How closely the mosaic matches its input; unknown when re-rendering.
+    pub quality: Option<MosaicQuality>,
+    /// Placements, when `MosaicSpec::manifest` is set.
+    pub manifest: Option<Manifest>,
+}
+
+/// How closely a mosaic reproduces its input, with both shrunk to a few
+/// pixels per cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MosaicQuality {
+    /// Peak signal-to-noise ratio in dB; higher is closer.
+    pub psnr: f64,
+    /// Mean structural similarity of luma, up to 1.0 for identical images.
+    pub ssim: f64,
+    /// Mean CIE76 color difference; around 2.3 is just noticeable.
+    pub mean_delta_e: f64,
+}
+
+/// Everything needed to tell which tile landed in which cell of a mosaic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub tile_size: u32,
+    pub grid_width: u32,
+    pub grid_height: u32,
+    pub color_space: ColorSpace,
+    pub matte: [u8; 3],
+    pub transparent: bool,
+    /// Seed the tiles were sampled with, so the mosaic can be generated again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// One entry per drawn tile in the order they were matched; cells kept
+    /// transparent are absent.
+    pub placements: Vec<Placement>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Placement {
+    pub row: u32,
+    pub column: u32,
+    /// Cells the tile covers downwards and rightwards from `row`, `column`.
+    #[serde(default = "single_cell")]
+    pub row_span: u32,
+    #[serde(default = "single_cell")]
+    pub column_span: u32,
+    /// Pixel rectangle the tile covers in the output.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Catalog id, or for directory tiles the id an import would assign. When
+    /// rendering, a catalog id takes precedence over `tile_path`.
+    pub tile_id: String,
+    /// The file the tile was read from.
+    pub tile_path: PathBuf,
+    /// Average color of the cell (or pinned region) the tile was matched against.
+    pub cell_color: [u8; 3],
+    /// Average color of the tile as drawn.
+    pub tile_color: [u8; 3],
+    /// RGB distance between `cell_color` and `tile_color`.
+    pub distance: f32,
+    pub transform: TileTransform,
+    /// Per-channel offset added to the tile's pixels, when one was applied.
+    pub color_adjustment: Option<[i16; 3]>,
+}
+
+/// How the tile image was fitted to its cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TileTransform {
+    /// Scaled to the cell size without cropping or rotation.
+    #[default]
+    Stretch,
+}
+
+/// Shape of an image written band by band: 8-bit RGB, or RGBA when `alpha` is set.
+#[derive(Debug, Clone, Copy)]
+pub struct RasterLayout {
+    pub width: u32,
+    pub height: u32,
+    pub alpha: bool,
+}
+
+fn single_cell() -> u32 {
+    1
+}
+
+impl CellRegion {
+    pub fn cell(row: u32, column: u32) -> Self {
+        CellRegion {
+            top: row,
+            left: column,
+            bottom: row,
+            right: column,
+        }
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.bottom - self.top + 1
+    }
+
+    pub fn columns(&self) -> u32 {
+        self.right - self.left + 1
+    }
+
+    pub fn contains(&self, row: u32, column: u32) -> bool {
+        (self.top..=self.bottom).contains(&row) && (self.left..=self.right).contains(&column)
+    }
+
+    pub fn overlaps(&self, other: &CellRegion) -> bool {
+        self.top <= other.bottom
+            && other.top <= self.bottom
+            && self.left <= other.right
+            && other.left <= self.right
+    }
+}
+
+impl fmt::Display for CellRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rows() == 1 && self.columns() == 1 {
+            write!(f, "{},{}", self.top, self.left)
+        } else {
+            write!(f, "{},{}:{},{}", self.top, self.left, self.bottom, self.right)
+        }
+    }
+}
+
+impl DetailSpec {
+    pub const DEFAULT_LEVELS: u32 = 3;
+}
+
+impl FromStr for DetailSource {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.trim().is_empty() {
+            return Err("detail source must be auto or an image path".to_string());
+        }
+        if value.eq_ignore_ascii_case("auto") {
+            Ok(DetailSource::Auto)
+        } else {
+            Ok(DetailSource::Map(PathBuf::from(value)))
+        }
+    }
+}
+
+impl MatchMetric {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MatchMetric::Rgb => "rgb",
+            MatchMetric::Lab => "lab",
+        }
+    }
+}
+
+impl fmt::Display for MatchMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for MatchMetric {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "rgb" => Ok(MatchMetric::Rgb),
+            "lab" => Ok(MatchMetric::Lab),
+            other => Err(format!("unknown match metric: {other} (expected rgb or lab)")),
+        }
+    }
+}
+
+impl DitherKernel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DitherKernel::FloydSteinberg => "floyd-steinberg",
+            DitherKernel::Atkinson => "atkinson",
+        }
+    }
+}
+
+impl fmt::Display for DitherKernel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DitherKernel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "floyd-steinberg" | "fs" => Ok(DitherKernel::FloydSteinberg),
+            "atkinson" => Ok(DitherKernel::Atkinson),
+            other => Err(format!(
+                "unknown dither kernel: {other} (expected floyd-steinberg or atkinson)"
+            )),
+        }
+    }
+}
+
+/// Parses `ROW,COL` for one cell or `ROW,COL:ROW,COL` for two opposite corners.
+impl FromStr for CellRegion {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let cell = |text: &str| -> Result<(u32, u32), String> {
+            let (row, column) = text
+                .split_once(',')
+                .ok_or_else(|| format!("invalid cell: {text} (expected ROW,COL)"))?;
+            let parse = |part: &str| {
+                part.trim()
+                    .parse::<u32>()
+                    .map_err(|_| format!("invalid cell: {text} (expected ROW,COL)"))
+            };
+            Ok((parse(row)?, parse(column)?))
+        };
+
+        let (first, second) = match value.split_once(':') {
+            Some((first, second)) => (cell(first)?, cell(second)?),
+            None => {
+                let only = cell(value)?;
+                (only, only)
+            }
+        };
+        Ok(CellRegion {
+            top: first.0.min(second.0),
+            left: first.1.min(second.1),
+            bottom: first.0.max(second.0),
+            right: first.1.max(second.1),
+        })
+    }
+}
+
+/// Parses `TILE_ID@REGION`.
+impl FromStr for TilePin {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (tile_id, region) = value
+            .split_once('@')
+            .ok_or_else(|| format!("invalid pin: {value} (expected TILE_ID@ROW,COL[:ROW,COL])"))?;
+        if tile_id.trim().is_empty() {
+            return Err(format!("invalid pin: {value} (missing tile id)"));
+        }
+        Ok(TilePin {
+            tile_id: tile_id.trim().to_string(),
+            region: region.parse()?,
+        })
+    }
+}
+
+/// Parses `TILE_ID` or `TILE_ID@REGION`.
+impl FromStr for TileBan {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (tile_id, region) = match value.split_once('@') {
+            Some((tile_id, region)) => (tile_id, Some(region.parse()?)),
+            None => (value, None),
+        };
+        if tile_id.trim().is_empty() {
+            return Err(format!("invalid ban: {value} (missing tile id)"));
+        }
+        Ok(TileBan {
+            tile_id: tile_id.trim().to_string(),
+            region,
+        })
+    }
+}
+
+impl PngFilter {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PngFilter::None => "none",
+            PngFilter::Sub => "sub",
+            PngFilter::Up => "up",
+            PngFilter::Avg => "avg",
+            PngFilter::Paeth => "paeth",
+            PngFilter::Adaptive => "adaptive",
+        }
+    }
+}
+
+impl fmt::Display for PngFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PngFilter {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Ok(PngFilter::None),
+            "sub" => Ok(PngFilter::Sub),
+            "up" => Ok(PngFilter::Up),
+            "avg" | "average" => Ok(PngFilter::Avg),
+            "paeth" => Ok(PngFilter::Paeth),
+            "adaptive" => Ok(PngFilter::Adaptive),
+            other => Err(format!(
+                "unknown png filter: {other} (expected none, sub, up, avg, paeth or adaptive)"
+            )),
+        }
+    }
+}
+
+impl TileTransform {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TileTransform::Stretch => "stretch",
+        }
+    }
+}
+
+impl fmt::Display for TileTransform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_parse_as_cells_or_corner_pairs() {
+        assert_eq!("3,4".parse(), Ok(CellRegion::cell(3, 4)));
+        let region: CellRegion = " 5, 1 : 2,7".parse().unwrap();
+        assert_eq!(
+            region,
+            CellRegion {
+                top: 2,
+                left: 1,
+                bottom: 5,
+                right: 7,
+            }
+        );
+        assert_eq!((region.rows(), region.columns()), (4, 7));
+        assert!(region.contains(2, 7) && region.contains(5, 1));
+        assert!(!region.contains(1, 1) && !region.contains(2, 8));
+
+        assert!("3".parse::<CellRegion>().is_err());
+        assert!("3,x".parse::<CellRegion>().is_err());
+        assert!("1,1:2".parse::<CellRegion>().is_err());
+        assert!("-1,0".parse::<CellRegion>().is_err());
+    }
+
+    #[test]
+    fn pins_need_a_tile_and_a_region() {
+        let pin: TilePin = "abc@0,0:1,1".parse().unwrap();
+        assert_eq!(pin.tile_id, "abc");
+        assert_eq!((pin.region.rows(), pin.region.columns()), (2, 2));
+        assert!("abc".parse::<TilePin>().is_err());
+        assert!(" @1,1".parse::<TilePin>().is_err());
+        assert!("abc@1".parse::<TilePin>().is_err());
+    }
+
+    #[test]
+    fn bans_cover_a_region_or_everything() {
+        let ban: TileBan = "abc".parse().unwrap();
+        assert_eq!(ban.region, None);
+        let ban: TileBan = "abc@2,3".parse().unwrap();
+        assert_eq!(ban.region, Some(CellRegion::cell(2, 3)));
+        assert!("@2,3".parse::<TileBan>().is_err());
+        assert!("abc@".parse::<TileBan>().is_err());
+    }
 }
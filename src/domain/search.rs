@@ -0,0 +1,29 @@
+use crate::domain::Tile;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    /// Rank tiles by distance to this color.
+    pub color: Option<[u8; 3]>,
+    /// Substring, or glob when it contains `*`, `?` or `[`.
+    pub path: Option<String>,
+    /// Tags a tile must all carry.
+    pub tags: Vec<String>,
+    pub min_width: Option<u32>,
+    pub max_width: Option<u32>,
+    pub min_height: Option<u32>,
+    pub max_height: Option<u32>,
+    /// Unix timestamps bounding the import date.
+    pub imported_after: Option<i64>,
+    pub imported_before: Option<i64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub tile: Tile,
+    /// RGB distance to the query color, when one was given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f32>,
+}
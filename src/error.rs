@@ -10,6 +10,8 @@ pub enum AppError {
     Config(#[from] toml::de::Error),
     #[error("config write error: {0}")]
     ConfigWrite(#[from] toml::ser::Error),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
     #[error("config file not found: {0}")]
     ConfigMissing(String),
     #[error("invalid input: {0}")]
@@ -0,0 +1,72 @@
+use crate::domain::{Catalog, CatalogBundle};
+use crate::error::{AppError, AppResult};
+use image::{ImageFormat, RgbImage};
+use std::fs::{self, File};
+use std::io::{Cursor, Read};
+use std::path::Path;
+use tar::{Archive, Builder, Header};
+
+const CATALOG_ENTRY: &str = "catalog.toml";
+const THUMBNAIL_DIR: &str = "thumbnails";
+
+pub fn write_bundle(path: &Path, bundle: &CatalogBundle) -> AppResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut builder = Builder::new(File::create(path)?);
+    let contents = toml::to_string_pretty(&bundle.catalog)?;
+    append_entry(&mut builder, CATALOG_ENTRY, contents.as_bytes())?;
+
+    for (id, thumbnail) in &bundle.thumbnails {
+        let mut bytes = Vec::new();
+        thumbnail.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+        append_entry(&mut builder, &format!("{THUMBNAIL_DIR}/{id}.png"), &bytes)?;
+    }
+
+    builder.into_inner()?;
+    Ok(())
+}
+
+pub fn read_bundle(path: &Path) -> AppResult<CatalogBundle> {
+    let mut archive = Archive::new(File::open(path)?);
+    let mut catalog: Option<Catalog> = None;
+    let mut thumbnails = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_path_buf();
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+
+        if entry_path == Path::new(CATALOG_ENTRY) {
+            let contents = String::from_utf8_lossy(&bytes);
+            catalog = Some(toml::from_str(&contents)?);
+        } else if entry_path.starts_with(THUMBNAIL_DIR) {
+            let Some(id) = entry_path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let thumbnail: RgbImage =
+                image::load_from_memory_with_format(&bytes, ImageFormat::Png)?.to_rgb8();
+            thumbnails.push((id.to_string(), thumbnail));
+        }
+    }
+
+    let catalog = catalog.ok_or_else(|| {
+        AppError::InvalidInput(format!("archive does not contain {CATALOG_ENTRY}"))
+    })?;
+
+    Ok(CatalogBundle {
+        catalog,
+        thumbnails,
+    })
+}
+
+fn append_entry(builder: &mut Builder<File>, name: &str, bytes: &[u8]) -> AppResult<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, name, bytes)?;
+    Ok(())
+}
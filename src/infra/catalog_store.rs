@@ -38,4 +38,11 @@ impl CatalogStore for TomlCatalogStore {
         fs::write(&self.path, contents)?;
         Ok(())
     }
+
+    fn thumbnail_dir(&self) -> PathBuf {
+        match self.path.parent() {
+            Some(parent) => parent.join("thumbnails"),
+            None => PathBuf::from("thumbnails"),
+        }
+    }
 }
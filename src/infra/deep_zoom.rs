@@ -0,0 +1,495 @@
+use crate::app::traits::ImageIo;
+use crate::domain::{OutputOptions, RasterLayout};
+use crate::error::{AppError, AppResult};
+use crate::infra::image_io::{check_dpi, ImageIoImpl};
+use image::{ImageFormat, RgbImage, RgbaImage};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Edge length of pyramid tiles. Tiles do not overlap.
+const TILE_SIZE: u32 = 256;
+const VIEWER_TEMPLATE: &str = include_str!("viewer.html");
+const DEEP_ZOOM_NAMESPACE: &str = "http://schemas.microsoft.com/deepzoom/2008";
+
+pub fn is_deep_zoom(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("dzi"))
+}
+
+/// Writes `<name>.dzi`, the `<name>_files/<level>/<col>_<row>` tile pyramid and
+/// a `<name>.html` viewer that works straight from disk.
+///
+/// Rows arrive band by band; each level keeps at most one row of tiles in
+/// memory and hands every pair of rows, averaged, to the level below it.
+pub fn write_pyramid(
+    image_io: &ImageIoImpl,
+    path: &Path,
+    layout: &RasterLayout,
+    bands: &mut dyn Iterator<Item = AppResult<Vec<u8>>>,
+    options: &OutputOptions,
+) -> AppResult<()> {
+    let format = tile_format(layout.alpha, options)?;
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| {
+            AppError::InvalidInput(format!("invalid output path {}", path.display()))
+        })?;
+    let parent = path.parent().unwrap_or(Path::new(""));
+    let tiles_dir = tiles_dir(path);
+    // Tiles from an earlier, larger render would otherwise linger.
+    for level in earlier_levels(path)? {
+        fs::remove_dir_all(level)?;
+    }
+
+    let writer = TileWriter {
+        image_io,
+        dir: tiles_dir,
+        format,
+        extension: format.extensions_str()[0],
+        alpha: layout.alpha,
+        options: OutputOptions {
+            format: Some(format),
+            ..options.clone()
+        },
+    };
+
+    let max_level = max_level(layout.width, layout.height);
+    let mut levels: Vec<Level> = (0..=max_level)
+        .map(|level| {
+            let scale = 1u64 << (max_level - level);
+            Level {
+                index: level,
+                width: (layout.width as u64).div_ceil(scale) as u32,
+                channels: writer.channels(),
+                pending: Vec::new(),
+                carry: None,
+                next_row: 0,
+            }
+        })
+        .collect();
+
+    for band in bands {
+        let mut rows = band?;
+        for level in levels.iter_mut().rev() {
+            rows = level.push(&rows, &writer)?;
+            if rows.is_empty() {
+                break;
+            }
+        }
+    }
+
+    let mut rows = Vec::new();
+    for level in levels.iter_mut().rev() {
+        let mut below = level.push(&rows, &writer)?;
+        below.extend(level.finish(&writer)?);
+        rows = below;
+    }
+
+    fs::write(path, dzi_descriptor(layout, writer.extension))?;
+    fs::write(
+        parent.join(format!("{name}.html")),
+        viewer_html(name, layout, writer.extension, max_level),
+    )?;
+    Ok(())
+}
+
+/// The level directories of a pyramid already written to `path`. Fails if
+/// `<name>_files` exists but does not hold just the levels of a pyramid with
+/// a `<name>.dzi` descriptor, so nothing else is ever deleted.
+pub fn earlier_levels(path: &Path) -> AppResult<Vec<PathBuf>> {
+    let tiles_dir = tiles_dir(path);
+    if !tiles_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let foreign = || {
+        AppError::InvalidInput(format!(
+            "{} exists but is not a Deep Zoom pyramid; move it or choose another output",
+            tiles_dir.display()
+        ))
+    };
+    let descriptor = fs::read_to_string(path).unwrap_or_default();
+    if !tiles_dir.is_dir() || !descriptor.contains(DEEP_ZOOM_NAMESPACE) {
+        return Err(foreign());
+    }
+
+    let mut levels = Vec::new();
+    for entry in fs::read_dir(&tiles_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() || !is_number(&entry.file_name().to_string_lossy()) {
+            return Err(foreign());
+        }
+        for tile in fs::read_dir(entry.path())? {
+            let tile = tile?;
+            if !tile.file_type()?.is_file() || !is_tile_name(&tile.file_name().to_string_lossy()) {
+                return Err(foreign());
+            }
+        }
+        levels.push(entry.path());
+    }
+    Ok(levels)
+}
+
+fn tiles_dir(path: &Path) -> PathBuf {
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{name}_files"))
+}
+
+fn is_number(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+/// `<col>_<row>.<extension>`, as `TileWriter` names tiles.
+fn is_tile_name(name: &str) -> bool {
+    let Some((position, extension)) = name.rsplit_once('.') else {
+        return false;
+    };
+    let Some((col, row)) = position.split_once('_') else {
+        return false;
+    };
+    is_number(col) && is_number(row) && !extension.is_empty()
+}
+
+/// Pyramid tiles use the requested format, or JPEG (PNG with transparency).
+pub fn tile_format(alpha: bool, options: &OutputOptions) -> AppResult<ImageFormat> {
+    let format = options.format.unwrap_or(if alpha {
+        ImageFormat::Png
+    } else {
+        ImageFormat::Jpeg
+    });
+    if !format.writing_enabled() {
+        return Err(AppError::InvalidInput(format!(
+            "writing {format:?} is not supported by this build"
+        )));
+    }
+    if alpha && format == ImageFormat::Jpeg {
+        return Err(AppError::InvalidInput(
+            "jpeg cannot store transparency; use png or webp pyramid tiles".to_string(),
+        ));
+    }
+    check_dpi(format, options)?;
+    Ok(format)
+}
+
+/// The level at which the image is full size; level 0 is a single pixel.
+fn max_level(width: u32, height: u32) -> u32 {
+    let longest = width.max(height).max(1);
+    u32::BITS - (longest - 1).leading_zeros()
+}
+
+struct TileWriter<'a> {
+    image_io: &'a ImageIoImpl,
+    dir: PathBuf,
+    format: ImageFormat,
+    extension: &'static str,
+    alpha: bool,
+    options: OutputOptions,
+}
+
+impl TileWriter<'_> {
+    fn channels(&self) -> usize {
+        if self.alpha { 4 } else { 3 }
+    }
+
+    fn write(
+        &self,
+        level: u32,
+        (col, row): (u32, u32),
+        (width, height): (u32, u32),
+        samples: Vec<u8>,
+    ) -> AppResult<()> {
+        let path = self
+            .dir
+            .join(level.to_string())
+            .join(format!("{col}_{row}.{}", self.extension));
+        if self.alpha {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let image =
+                RgbaImage::from_raw(width, height, samples).expect("tile samples match size");
+            image.save_with_format(&path, self.format)?;
+            Ok(())
+        } else {
+            let image =
+                RgbImage::from_raw(width, height, samples).expect("tile samples match size");
+            self.image_io.write_rgb(&path, &image, &self.options)
+        }
+    }
+}
+
+struct Level {
+    index: u32,
+    width: u32,
+    channels: usize,
+    /// Rows not yet written as a full row of tiles.
+    pending: Vec<u8>,
+    /// An unpaired row waiting for its partner before being halved.
+    carry: Option<Vec<u8>>,
+    next_row: u32,
+}
+
+impl Level {
+    fn row_len(&self) -> usize {
+        self.width as usize * self.channels
+    }
+
+    /// Accepts whole rows; returns the half-resolution rows for the level below.
+    fn push(&mut self, rows: &[u8], writer: &TileWriter) -> AppResult<Vec<u8>> {
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        let row_len = self.row_len();
+        let mut below = Vec::new();
+        if self.index > 0 {
+            for row in rows.chunks_exact(row_len) {
+                match self.carry.take() {
+                    Some(previous) => below.extend(self.halve(&previous, row)),
+                    None => self.carry = Some(row.to_vec()),
+                }
+            }
+        }
+
+        self.pending.extend_from_slice(rows);
+        let tile_bytes = TILE_SIZE as usize * row_len;
+        while self.pending.len() >= tile_bytes {
+            let remainder = self.pending.split_off(tile_bytes);
+            let full = std::mem::replace(&mut self.pending, remainder);
+            self.write_tile_row(&full, writer)?;
+        }
+        Ok(below)
+    }
+
+    /// Writes what is left and returns the last, unpaired row halved on its own.
+    fn finish(&mut self, writer: &TileWriter) -> AppResult<Vec<u8>> {
+        if !self.pending.is_empty() {
+            let rest = std::mem::take(&mut self.pending);
+            self.write_tile_row(&rest, writer)?;
+        }
+        Ok(match self.carry.take() {
+            Some(row) => self.halve(&row, &row),
+            None => Vec::new(),
+        })
+    }
+
+    fn write_tile_row(&mut self, rows: &[u8], writer: &TileWriter) -> AppResult<()> {
+        let row_len = self.row_len();
+        let height = (rows.len() / row_len) as u32;
+        for col in 0..self.width.div_ceil(TILE_SIZE) {
+            let x = col * TILE_SIZE;
+            let width = TILE_SIZE.min(self.width - x);
+            let start = x as usize * self.channels;
+            let end = start + width as usize * self.channels;
+            let samples: Vec<u8> = rows
+                .chunks_exact(row_len)
+                .flat_map(|row| &row[start..end])
+                .copied()
+                .collect();
+            writer.write(self.index, (col, self.next_row), (width, height), samples)?;
+        }
+        self.next_row += 1;
+        Ok(())
+    }
+
+    /// Box-filters two rows of this level into one row of the level below.
+    fn halve(&self, top: &[u8], bottom: &[u8]) -> Vec<u8> {
+        let channels = self.channels;
+        let half_width = self.width.div_ceil(2) as usize;
+        let mut row = Vec::with_capacity(half_width * channels);
+        for x in 0..half_width {
+            let left = 2 * x;
+            let right = (2 * x + 1).min(self.width as usize - 1);
+            for channel in 0..channels {
+                let sum = top[left * channels + channel] as u32
+                    + top[right * channels + channel] as u32
+                    + bottom[left * channels + channel] as u32
+                    + bottom[right * channels + channel] as u32;
+                row.push(((sum + 2) / 4) as u8);
+            }
+        }
+        row
+    }
+}
+
+fn dzi_descriptor(layout: &RasterLayout, extension: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <Image xmlns=\"{DEEP_ZOOM_NAMESPACE}\" \
+         Format=\"{extension}\" Overlap=\"0\" TileSize=\"{TILE_SIZE}\">\n  \
+         <Size Width=\"{}\" Height=\"{}\"/>\n</Image>\n",
+        layout.width, layout.height
+    )
+}
+
+/// The viewer embeds the pyramid description so it needs no fetch, which
+/// browsers refuse for `file://` pages.
+fn viewer_html(name: &str, layout: &RasterLayout, extension: &str, max_level: u32) -> String {
+    VIEWER_TEMPLATE
+        .replace("{{TITLE}}", &html_escape(name))
+        .replace("{{TILES}}", &js_string(&format!("{name}_files")))
+        .replace("{{WIDTH}}", &layout.width.to_string())
+        .replace("{{HEIGHT}}", &layout.height.to_string())
+        .replace("{{TILE_SIZE}}", &TILE_SIZE.to_string())
+        .replace("{{MAX_LEVEL}}", &max_level.to_string())
+        .replace("{{EXTENSION}}", &js_string(extension))
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// A JSON string literal that cannot close the surrounding `<script>`.
+fn js_string(value: &str) -> String {
+    serde_json::to_string(value)
+        .unwrap_or_else(|_| "\"\"".to_string())
+        .replace("</", "<\\/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("andreamosaic-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_gray(path: &Path, (width, height): (u32, u32), band_rows: usize) -> AppResult<()> {
+        let layout = RasterLayout {
+            width,
+            height,
+            alpha: false,
+        };
+        let row = vec![200u8; width as usize * 3];
+        let rows: Vec<Vec<u8>> = vec![row; height as usize];
+        let mut bands = rows.chunks(band_rows).map(|band| Ok(band.concat()));
+        let options = OutputOptions {
+            format: Some(ImageFormat::Png),
+            ..OutputOptions::default()
+        };
+        write_pyramid(&ImageIoImpl::new(), path, &layout, &mut bands, &options)
+    }
+
+    #[test]
+    fn max_level_halves_down_to_one_pixel() {
+        assert_eq!(max_level(1, 1), 0);
+        assert_eq!(max_level(2, 1), 1);
+        assert_eq!(max_level(256, 256), 8);
+        assert_eq!(max_level(257, 10), 9);
+        assert_eq!(max_level(640, 480), 10);
+    }
+
+    #[test]
+    fn halve_averages_pairs_and_repeats_the_last_odd_column() {
+        let level = Level {
+            index: 1,
+            width: 3,
+            channels: 1,
+            pending: Vec::new(),
+            carry: None,
+            next_row: 0,
+        };
+        assert_eq!(level.halve(&[0, 100, 50], &[100, 200, 50]), vec![100, 50]);
+    }
+
+    #[test]
+    fn pyramid_has_a_tile_grid_per_level() {
+        let dir = scratch_dir("pyramid");
+        let path = dir.join("m.dzi");
+        write_gray(&path, (300, 10), 4).unwrap();
+
+        let tiles_dir = dir.join("m_files");
+        let tile = |level: u32, name: &str| {
+            image::open(tiles_dir.join(level.to_string()).join(name)).unwrap().to_rgb8()
+        };
+        let full = tile(9, "0_0.png");
+        assert_eq!(full.dimensions(), (256, 10));
+        assert_eq!(full.get_pixel(0, 0).0, [200, 200, 200]);
+        assert_eq!(tile(9, "1_0.png").dimensions(), (44, 10));
+        assert!(!tiles_dir.join("9").join("2_0.png").exists());
+        assert_eq!(tile(8, "0_0.png").dimensions(), (150, 5));
+        assert_eq!(tile(7, "0_0.png").dimensions(), (75, 3));
+        assert_eq!(tile(0, "0_0.png").dimensions(), (1, 1));
+        assert_eq!(fs::read_dir(&tiles_dir).unwrap().count(), 10);
+
+        let descriptor = fs::read_to_string(&path).unwrap();
+        assert!(descriptor.contains("Width=\"300\" Height=\"10\""));
+        assert!(dir.join("m.html").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rewriting_replaces_only_earlier_levels() {
+        let dir = scratch_dir("rewrite");
+        let path = dir.join("m.dzi");
+        write_gray(&path, (600, 4), 4).unwrap();
+        write_gray(&path, (100, 4), 4).unwrap();
+
+        let levels = fs::read_dir(dir.join("m_files")).unwrap().count();
+        assert_eq!(levels, max_level(100, 4) as usize + 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn foreign_files_directory_is_left_alone() {
+        let dir = scratch_dir("foreign");
+        let path = dir.join("m.dzi");
+        fs::create_dir_all(dir.join("m_files").join("notes")).unwrap();
+        fs::write(dir.join("m_files").join("notes").join("a.txt"), "keep").unwrap();
+        assert!(matches!(earlier_levels(&path), Err(AppError::InvalidInput(_))));
+
+        fs::write(&path, "not a descriptor").unwrap();
+        fs::remove_dir_all(dir.join("m_files").join("notes")).unwrap();
+        fs::create_dir_all(dir.join("m_files").join("0")).unwrap();
+        assert!(matches!(earlier_levels(&path), Err(AppError::InvalidInput(_))));
+
+        fs::remove_dir_all(dir.join("m_files")).unwrap();
+        write_gray(&path, (2, 2), 2).unwrap();
+        fs::write(dir.join("m_files").join("1").join("notes.txt"), "keep").unwrap();
+        assert!(matches!(earlier_levels(&path), Err(AppError::InvalidInput(_))));
+        assert!(write_gray(&path, (2, 2), 2).is_err());
+        assert!(dir.join("m_files").join("1").join("notes.txt").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tile_names_are_column_row_and_extension() {
+        assert!(is_tile_name("0_0.jpeg"));
+        assert!(is_tile_name("12_3.png"));
+        assert!(!is_tile_name("0_0"));
+        assert!(!is_tile_name("a_0.png"));
+        assert!(!is_tile_name("0.png"));
+        assert!(!is_tile_name("0_0."));
+    }
+
+    #[test]
+    fn tile_formats_follow_alpha_unless_set() {
+        let options = OutputOptions::default();
+        assert_eq!(tile_format(false, &options).unwrap(), ImageFormat::Jpeg);
+        assert_eq!(tile_format(true, &options).unwrap(), ImageFormat::Png);
+
+        let jpeg = OutputOptions {
+            format: Some(ImageFormat::Jpeg),
+            ..OutputOptions::default()
+        };
+        assert!(tile_format(true, &jpeg).is_err());
+    }
+
+    #[test]
+    fn only_png_and_jpeg_tiles_take_a_dpi() {
+        let options = |format| OutputOptions {
+            format,
+            dpi: Some(300),
+            ..OutputOptions::default()
+        };
+        assert!(tile_format(false, &options(None)).is_ok());
+        assert!(tile_format(true, &options(None)).is_ok());
+        assert!(tile_format(false, &options(Some(ImageFormat::WebP))).is_err());
+    }
+}
@@ -1,8 +1,56 @@
 use crate::app::traits::ImageIo;
-use crate::error::AppResult;
-use image::{DynamicImage, ImageFormat};
+use crate::domain::{ExifInfo, OutputOptions, PngFilter, RasterLayout};
+use crate::error::{AppError, AppResult};
+use crate::infra::deep_zoom;
+use chrono::NaiveDateTime;
+use exif::{In, Reader, Tag, Value};
+use image::codecs::jpeg::{JpegEncoder, PixelDensity};
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, RgbImage, RgbaImage};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
 
+/// Enough leading bytes to recognise every signature `image::guess_format` knows.
+const SNIFF_LEN: usize = 32;
+
+const DEFAULT_JPEG_QUALITY: u8 = 90;
+const METERS_PER_INCH: f64 = 0.0254;
+
+/// Formats `write_bands` encodes without holding the whole image.
+#[cfg(feature = "tiff")]
+const STREAMED_FORMATS: &[ImageFormat] = &[ImageFormat::Png, ImageFormat::Tiff];
+#[cfg(not(feature = "tiff"))]
+const STREAMED_FORMATS: &[ImageFormat] = &[ImageFormat::Png];
+
+/// Formats with no magic bytes, which can only be recognised by extension.
+const UNSIGNED_FORMATS: &[ImageFormat] = &[ImageFormat::Tga];
+
+/// Every format the compiled `image` features can decode.
+pub fn supported_formats() -> Vec<ImageFormat> {
+    ImageFormat::all()
+        .filter(|format| format.reading_enabled())
+        .collect()
+}
+
+/// Identifies a decodable image by its leading bytes, falling back to the
+/// extension only for formats that carry no signature.
+pub fn sniff_format(path: &Path) -> Option<ImageFormat> {
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)
+        .ok()?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut header)
+        .ok()?;
+
+    let format = image::guess_format(&header).ok().or_else(|| {
+        ImageFormat::from_path(path)
+            .ok()
+            .filter(|format| UNSIGNED_FORMATS.contains(format))
+    })?;
+    format.reading_enabled().then_some(format)
+}
+
 pub struct ImageIoImpl;
 
 impl ImageIoImpl {
@@ -13,17 +61,307 @@ impl ImageIoImpl {
 
 impl ImageIo for ImageIoImpl {
     fn read(&self, path: &Path) -> AppResult<DynamicImage> {
-        let image = image::open(path)?;
+        let mut decoder = ImageReader::open(path)?
+            .with_guessed_format()?
+            .into_decoder()?;
+        // Cameras usually store rotation as a tag instead of rotating the pixels.
+        let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+        let mut image = DynamicImage::from_decoder(decoder)?;
+        image.apply_orientation(orientation);
         Ok(image)
     }
 
-    fn write_rgb(&self, path: &Path, image: &image::RgbImage) -> AppResult<()> {
-        let dyn_image = DynamicImage::ImageRgb8(image.clone());
-        let format = ImageFormat::from_path(path).ok();
+    fn detect_format(&self, path: &Path) -> Option<ImageFormat> {
+        sniff_format(path)
+    }
+
+    fn read_exif(&self, path: &Path) -> AppResult<ExifInfo> {
+        let mut reader = BufReader::new(File::open(path)?);
+        // Files without EXIF data (or formats that cannot carry it) are not errors.
+        let Ok(exif) = Reader::new().read_from_container(&mut reader) else {
+            return Ok(ExifInfo::default());
+        };
+
+        let captured_at = [Tag::DateTimeOriginal, Tag::DateTime]
+            .into_iter()
+            .filter_map(|tag| exif.get_field(tag, In::PRIMARY))
+            .find_map(|field| match &field.value {
+                Value::Ascii(values) => values.first().and_then(|bytes| parse_exif_date(bytes)),
+                _ => None,
+            });
+
+        let orientation = exif
+            .get_field(Tag::Orientation, In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0))
+            .and_then(|value| u8::try_from(value).ok())
+            .filter(|value| (1..=8).contains(value));
+
+        Ok(ExifInfo {
+            captured_at,
+            orientation,
+        })
+    }
+
+    fn write_rgb(
+        &self,
+        path: &Path,
+        image: &RgbImage,
+        options: &OutputOptions,
+    ) -> AppResult<()> {
+        let format = output_format(path, options)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match format {
+            ImageFormat::Png => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                let encoder =
+                    png_encoder(&mut writer, image.width(), image.height(), false, options);
+                let mut png_writer = encoder.write_header().map_err(png_error)?;
+                png_writer.write_image_data(image.as_raw()).map_err(png_error)?;
+                png_writer.finish().map_err(png_error)?;
+                writer.flush()?;
+                Ok(())
+            }
+            ImageFormat::Jpeg => write_jpeg(path, image, options),
+            _ => {
+                image.save_with_format(path, format)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn can_stream(&self, path: &Path, options: &OutputOptions, alpha: bool) -> AppResult<bool> {
+        if deep_zoom::is_deep_zoom(path) {
+            deep_zoom::tile_format(alpha, options)?;
+            deep_zoom::earlier_levels(path)?;
+            return Ok(true);
+        }
+        let format = output_format(path, options)?;
+        check_alpha(format, alpha)?;
+        Ok(STREAMED_FORMATS.contains(&format))
+    }
+
+    fn is_pyramid(&self, path: &Path) -> bool {
+        deep_zoom::is_deep_zoom(path)
+    }
+
+    fn write_bands(
+        &self,
+        path: &Path,
+        layout: &RasterLayout,
+        bands: &mut dyn Iterator<Item = AppResult<Vec<u8>>>,
+        options: &OutputOptions,
+    ) -> AppResult<()> {
+        if deep_zoom::is_deep_zoom(path) {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            return deep_zoom::write_pyramid(self, path, layout, bands, options);
+        }
+
+        let format = output_format(path, options)?;
+        check_alpha(format, layout.alpha)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
         match format {
-            Some(fmt) => dyn_image.save_with_format(path, fmt)?,
-            None => dyn_image.save(path)?,
+            ImageFormat::Png => write_png_bands(path, layout, bands, options),
+            #[cfg(feature = "tiff")]
+            ImageFormat::Tiff if layout.alpha => {
+                write_tiff_bands::<tiff::encoder::colortype::RGBA8>(path, layout, bands)
+            }
+            #[cfg(feature = "tiff")]
+            ImageFormat::Tiff => {
+                write_tiff_bands::<tiff::encoder::colortype::RGB8>(path, layout, bands)
+            }
+            _ => {
+                // No incremental encoder: assemble the whole image first.
+                let mut samples = Vec::new();
+                for band in bands {
+                    samples.extend(band?);
+                }
+                if layout.alpha {
+                    let image = RgbaImage::from_raw(layout.width, layout.height, samples)
+                        .ok_or_else(|| band_size_error(layout))?;
+                    image.save_with_format(path, format)?;
+                    Ok(())
+                } else {
+                    let image = RgbImage::from_raw(layout.width, layout.height, samples)
+                        .ok_or_else(|| band_size_error(layout))?;
+                    self.write_rgb(path, &image, options)
+                }
+            }
+        }
+    }
+}
+
+fn png_encoder<W: Write>(
+    writer: W,
+    width: u32,
+    height: u32,
+    alpha: bool,
+    options: &OutputOptions,
+) -> png::Encoder<'static, W> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(if alpha {
+        png::ColorType::Rgba
+    } else {
+        png::ColorType::Rgb
+    });
+    encoder.set_depth(png::BitDepth::Eight);
+    if let Some(level) = options.png_compression {
+        encoder.set_deflate_compression(match level {
+            0 => png::DeflateCompression::NoCompression,
+            level => png::DeflateCompression::Level(level.min(9)),
+        });
+    }
+    if let Some(filter) = options.png_filter {
+        encoder.set_filter(match filter {
+            PngFilter::None => png::Filter::NoFilter,
+            PngFilter::Sub => png::Filter::Sub,
+            PngFilter::Up => png::Filter::Up,
+            PngFilter::Avg => png::Filter::Avg,
+            PngFilter::Paeth => png::Filter::Paeth,
+            PngFilter::Adaptive => png::Filter::Adaptive,
+        });
+    }
+    if let Some(dpi) = options.dpi {
+        let pixels_per_meter = (dpi as f64 / METERS_PER_INCH).round() as u32;
+        encoder.set_pixel_dims(Some(png::PixelDimensions {
+            xppu: pixels_per_meter,
+            yppu: pixels_per_meter,
+            unit: png::Unit::Meter,
+        }));
+    }
+    encoder
+}
+
+fn write_png_bands(
+    path: &Path,
+    layout: &RasterLayout,
+    bands: &mut dyn Iterator<Item = AppResult<Vec<u8>>>,
+    options: &OutputOptions,
+) -> AppResult<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let encoder = png_encoder(&mut writer, layout.width, layout.height, layout.alpha, options);
+    let mut png_writer = encoder.write_header().map_err(png_error)?;
+    let mut stream = png_writer.stream_writer().map_err(png_error)?;
+    for band in bands {
+        stream.write_all(&band?)?;
+    }
+    stream.finish().map_err(png_error)?;
+    png_writer.finish().map_err(png_error)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes each band as one TIFF strip.
+#[cfg(feature = "tiff")]
+fn write_tiff_bands<C: tiff::encoder::colortype::ColorType<Inner = u8>>(
+    path: &Path,
+    layout: &RasterLayout,
+    bands: &mut dyn Iterator<Item = AppResult<Vec<u8>>>,
+) -> AppResult<()> {
+    let mut encoder = tiff::encoder::TiffEncoder::new(BufWriter::new(File::create(path)?))
+        .map_err(tiff_error)?;
+    let mut image = encoder
+        .new_image::<C>(layout.width, layout.height)
+        .map_err(tiff_error)?;
+    let row_len = layout.width as usize * C::BITS_PER_SAMPLE.len();
+    let mut first = true;
+    for band in bands {
+        let band = band?;
+        if first {
+            image
+                .rows_per_strip((band.len() / row_len) as u32)
+                .map_err(tiff_error)?;
+            first = false;
         }
-        Ok(())
+        image.write_strip(&band).map_err(tiff_error)?;
+    }
+    image.finish().map_err(tiff_error)?;
+    Ok(())
+}
+
+#[cfg(feature = "tiff")]
+fn tiff_error(err: tiff::TiffError) -> AppError {
+    match err {
+        tiff::TiffError::IoError(err) => AppError::Io(err),
+        other => AppError::InvalidInput(format!("tiff encoding failed: {other}")),
     }
 }
+
+fn band_size_error(layout: &RasterLayout) -> AppError {
+    AppError::InvalidInput(format!(
+        "row bands do not add up to a {}x{} image",
+        layout.width, layout.height
+    ))
+}
+
+fn write_jpeg(path: &Path, image: &RgbImage, options: &OutputOptions) -> AppResult<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let quality = options.jpeg_quality.unwrap_or(DEFAULT_JPEG_QUALITY).clamp(1, 100);
+    let mut encoder = JpegEncoder::new_with_quality(&mut writer, quality);
+    if let Some(dpi) = options.dpi {
+        encoder.set_pixel_density(PixelDensity::dpi(dpi.min(u16::MAX as u32) as u16));
+    }
+    encoder.encode_image(image)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn png_error(err: png::EncodingError) -> AppError {
+    match err {
+        png::EncodingError::IoError(err) => AppError::Io(err),
+        other => AppError::InvalidInput(format!("png encoding failed: {other}")),
+    }
+}
+
+/// Picks the encoder from the explicit option or the output extension.
+fn output_format(path: &Path, options: &OutputOptions) -> AppResult<ImageFormat> {
+    let format = match options.format {
+        Some(format) => format,
+        None => ImageFormat::from_path(path).map_err(|_| {
+            AppError::InvalidInput(format!(
+                "cannot infer an output format from {}; use a known extension or --format",
+                path.display()
+            ))
+        })?,
+    };
+
+    if !format.writing_enabled() {
+        return Err(AppError::InvalidInput(format!(
+            "writing {format:?} is not supported by this build"
+        )));
+    }
+    check_dpi(format, options)?;
+    Ok(format)
+}
+
+/// Only the PNG and JPEG encoders can store a print resolution.
+pub fn check_dpi(format: ImageFormat, options: &OutputOptions) -> AppResult<()> {
+    if options.dpi.is_some() && !matches!(format, ImageFormat::Png | ImageFormat::Jpeg) {
+        return Err(AppError::InvalidInput(format!(
+            "{format:?} cannot store a print resolution; drop dpi or write png or jpeg"
+        )));
+    }
+    Ok(())
+}
+
+fn check_alpha(format: ImageFormat, alpha: bool) -> AppResult<()> {
+    if alpha && format == ImageFormat::Jpeg {
+        return Err(AppError::InvalidInput(
+            "jpeg cannot store transparency; write png, webp or tiff instead".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn parse_exif_date(bytes: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    let date = NaiveDateTime::parse_from_str(text.trim(), "%Y:%m:%d %H:%M:%S").ok()?;
+    Some(date.format("%Y-%m-%dT%H:%M:%S").to_string())
+}
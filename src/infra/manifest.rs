@@ -0,0 +1,122 @@
+use crate::app::image_utils::hex_color;
+use crate::domain::Manifest;
+use crate::error::{AppError, AppResult};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    Json,
+    Csv,
+}
+
+/// Picks the manifest format from the file extension.
+pub fn manifest_format(path: &Path) -> AppResult<ManifestFormat> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("json") => Ok(ManifestFormat::Json),
+        Some("csv") => Ok(ManifestFormat::Csv),
+        _ => Err(AppError::InvalidInput(format!(
+            "manifest {} must end in .json or .csv",
+            path.display()
+        ))),
+    }
+}
+
+/// Reads a JSON manifest; CSV manifests lack the grid settings needed to render.
+pub fn read_manifest(path: &Path) -> AppResult<Manifest> {
+    if manifest_format(path)? == ManifestFormat::Csv {
+        return Err(AppError::InvalidInput(
+            "rendering needs a .json manifest; csv manifests only list placements".to_string(),
+        ));
+    }
+    let contents = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Writes the full manifest as JSON, or one CSV row per placement.
+pub fn write_manifest(path: &Path, manifest: &Manifest) -> AppResult<()> {
+    let format = manifest_format(path)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        ManifestFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, manifest)?;
+            writeln!(writer)?;
+        }
+        ManifestFormat::Csv => write_csv(&mut writer, manifest)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_csv(writer: &mut impl Write, manifest: &Manifest) -> AppResult<()> {
+    writeln!(
+        writer,
+        "row,column,row_span,column_span,x,y,width,height,tile_id,tile_path,cell_color,\
+         tile_color,distance,transform,color_adjustment"
+    )?;
+    for placement in &manifest.placements {
+        let adjustment = placement
+            .color_adjustment
+            .map(|[r, g, b]| format!("{r};{g};{b}"))
+            .unwrap_or_default();
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{:.2},{},{}",
+            placement.row,
+            placement.column,
+            placement.row_span,
+            placement.column_span,
+            placement.x,
+            placement.y,
+            placement.width,
+            placement.height,
+            placement.tile_id,
+            csv_field(&placement.tile_path.to_string_lossy()),
+            hex_color(placement.cell_color),
+            hex_color(placement.tile_color),
+            placement.distance,
+            placement.transform,
+            adjustment
+        )?;
+    }
+    Ok(())
+}
+
+/// Quotes a CSV field if it holds a comma, quote or newline.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_field("lib/a.png"), "lib/a.png");
+        assert_eq!(csv_field("lib/a,b.png"), "\"lib/a,b.png\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(manifest_format(Path::new("m.JSON")).unwrap(), ManifestFormat::Json);
+        assert_eq!(manifest_format(Path::new("out/m.csv")).unwrap(), ManifestFormat::Csv);
+        assert!(manifest_format(Path::new("m.txt")).is_err());
+        assert!(manifest_format(Path::new("m")).is_err());
+    }
+}
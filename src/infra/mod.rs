@@ -1,5 +1,8 @@
+pub mod archive;
 pub mod catalog_store;
+pub mod deep_zoom;
 pub mod image_io;
+pub mod manifest;
 
 pub use catalog_store::TomlCatalogStore;
 pub use image_io::ImageIoImpl;
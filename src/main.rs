@@ -6,10 +6,16 @@ mod error;
 mod infra;
 mod ui;
 
-use crate::cli::{CatalogCommands, Commands, GenerateArgs};
-use crate::domain::{MosaicSpec, TilesSource};
+use crate::app::image_utils::parse_hex_color;
+use crate::cli::{CatalogCommands, Commands, EncodingArgs, GenerateArgs, RenderArgs};
+use crate::domain::{
+    ColorSpace, CompareSpec, DetailSource, DetailSpec, DitherKernel, DitherSpec, GapSpec,
+    ImportOptions, MatchMetric, MosaicSpec, OutputOptions, QualityThresholds, RegionMask,
+    RenderSpec, SamplePool, SamplingSpec, ScanOptions, SearchQuery, TilesSource,
+};
 use crate::error::{AppError, AppResult};
 use clap::Parser;
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 
 fn main() -> anyhow::Result<()> {
@@ -36,9 +42,38 @@ fn main() -> anyhow::Result<()> {
             ui::run_tui(app, default_tile_size)?;
         }
         Some(Commands::Catalog { command }) => match command {
-            CatalogCommands::Add { path } => {
-                let added = app.catalog_add(&path)?;
-                cli::print_catalog_add(&added);
+            CatalogCommands::Add {
+                path,
+                hash,
+                skip_near_duplicates,
+                duplicate_threshold,
+                tags,
+                linear,
+                scan,
+                quality,
+            } => {
+                let options = ImportOptions {
+                    hash_kind: hash,
+                    color_space: color_space(linear),
+                    near_duplicate_threshold: skip_near_duplicates.then_some(duplicate_threshold),
+                    tags,
+                    scan: ScanOptions {
+                        include: scan.include,
+                        exclude: scan.exclude,
+                        max_depth: scan.max_depth,
+                        include_hidden: scan.hidden,
+                        symlinks: scan.symlinks,
+                    },
+                    quality: QualityThresholds {
+                        min_width: quality.min_width,
+                        min_height: quality.min_height,
+                        min_sharpness: quality.min_sharpness,
+                        max_underexposed: quality.max_underexposed,
+                        max_overexposed: quality.max_overexposed,
+                    },
+                };
+                let report = app.catalog_add(&path, &options)?;
+                cli::print_catalog_add(&report);
             }
             CatalogCommands::List => {
                 let catalog = app.catalog_list()?;
@@ -48,13 +83,121 @@ fn main() -> anyhow::Result<()> {
                 let removed = app.catalog_remove(&id)?;
                 cli::print_catalog_remove(&removed);
             }
+            CatalogCommands::Tag { id, tags, remove } => {
+                let tile = if remove {
+                    app.catalog_tag(&id, &[], &tags)?
+                } else {
+                    app.catalog_tag(&id, &tags, &[])?
+                };
+                cli::print_catalog_tag(&tile);
+            }
+            CatalogCommands::Search(args) => {
+                let query = SearchQuery {
+                    color: args.color,
+                    path: args.path,
+                    tags: args.tags,
+                    min_width: args.min_width,
+                    max_width: args.max_width,
+                    min_height: args.min_height,
+                    max_height: args.max_height,
+                    imported_after: args.imported_after,
+                    imported_before: args.imported_before,
+                    limit: args.limit,
+                };
+                let hits = app.catalog_search(&query)?;
+                cli::print_search_results(&hits, args.format)?;
+            }
+            CatalogCommands::Dedupe { hash, threshold } => {
+                let clusters = app.catalog_dedupe(hash, threshold)?;
+                cli::print_catalog_dedupe(&clusters);
+            }
+            CatalogCommands::Stats { levels } => {
+                let stats = app.catalog_stats(levels)?;
+                cli::print_catalog_stats(&stats);
+            }
+            CatalogCommands::Gaps {
+                input,
+                tile_size,
+                max_error,
+                limit,
+                swatches,
+                linear,
+                metric,
+                matte,
+                transparent,
+            } => {
+                let spec = GapSpec {
+                    input,
+                    tile_size: tile_size.unwrap_or(default_tile_size),
+                    max_error,
+                    limit,
+                    swatches,
+                    color_space: color_space(linear),
+                    metric,
+                    matte: matte.unwrap_or([0, 0, 0]),
+                    preserve_transparency: transparent,
+                };
+                let report = app.catalog_gaps(&spec)?;
+                cli::print_catalog_gaps(&report);
+            }
+            CatalogCommands::Root { path: None } => {
+                let catalog = app.catalog_list()?;
+                cli::print_catalog_root(&catalog);
+            }
+            CatalogCommands::Root { path: Some(path) } => {
+                let (root, relative) = app.catalog_set_root(&path)?;
+                cli::print_catalog_set_root(&root, relative);
+            }
+            CatalogCommands::Relocate { old_root, new_root } => {
+                let moved = app.catalog_relocate(&old_root, &new_root)?;
+                cli::print_catalog_relocate(moved);
+            }
+            CatalogCommands::Export {
+                archive,
+                thumbnails,
+                thumbnail_size,
+            } => {
+                let bundle = app.catalog_export(thumbnails.then_some(thumbnail_size))?;
+                infra::archive::write_bundle(&archive, &bundle)?;
+                cli::print_catalog_export(&archive, &bundle);
+            }
+            CatalogCommands::Import { archive, root } => {
+                let bundle = infra::archive::read_bundle(&archive)?;
+                let added = app.catalog_import(bundle, root.as_deref())?;
+                cli::print_catalog_import(&added);
+            }
         },
         Some(Commands::Generate(args)) => {
             let generate_config = file_config.generate.clone().unwrap_or_default();
             let spec = build_mosaic_spec(args, generate_config, default_tile_size)?;
             let result = app.generate_mosaic(&spec)?;
+            if let (Some(path), Some(manifest)) = (&spec.manifest, &result.manifest) {
+                infra::manifest::write_manifest(path, manifest)?;
+            }
             cli::print_generate_result(&result);
         }
+        Some(Commands::Render(args)) => {
+            let spec = build_render_spec(args)?;
+            let result = app.render_manifest(&spec)?;
+            cli::print_generate_result(&result);
+        }
+        Some(Commands::Compare(args)) => {
+            let generate_config = file_config.generate.clone().unwrap_or_default();
+            let base = build_mosaic_spec(args.generate, generate_config, default_tile_size)?;
+            if base.manifest.is_some() {
+                return Err(AppError::InvalidInput(
+                    "compare does not write manifests; generate a chosen setting instead"
+                        .to_string(),
+                )
+                .into());
+            }
+            let spec = CompareSpec {
+                base,
+                variations: args.variations,
+            };
+            let result = app.compare_settings(&spec)?;
+            cli::print_compare_result(&result);
+        }
     }
 
     Ok(())
@@ -65,6 +208,7 @@ fn build_mosaic_spec(
     file_config: config::GenerateConfig,
     default_tile_size: u32,
 ) -> AppResult<MosaicSpec> {
+    let output_options = output_options(args.encoding, &file_config)?;
     let input = args
         .input
         .or(file_config.input)
@@ -83,14 +227,250 @@ fn build_mosaic_spec(
     let tiles_value = args.tiles.or(file_config.tiles);
     let tiles_source = resolve_tiles_source(tiles_value)?;
 
+    let metric = match (args.metric, file_config.metric) {
+        (Some(metric), _) => metric,
+        (None, Some(value)) => value.parse::<MatchMetric>().map_err(AppError::InvalidInput)?,
+        (None, None) => MatchMetric::default(),
+    };
+
+    let matte = match (args.matte, file_config.matte) {
+        (Some(matte), _) => matte,
+        (None, Some(value)) => parse_hex_color(&value)?,
+        (None, None) => [0, 0, 0],
+    };
+
+    let memory_limit = match (args.memory_limit, file_config.memory_limit) {
+        (Some(limit), _) => Some(limit),
+        (None, Some(value)) => {
+            Some(cli::parse_byte_size(&value).map_err(AppError::InvalidInput)?)
+        }
+        (None, None) => None,
+    };
+
+    let regions = match (args.regions.is_empty(), file_config.regions) {
+        (true, Some(values)) => values
+            .iter()
+            .map(|value| cli::parse_mask_region(value).map_err(AppError::InvalidInput))
+            .collect::<AppResult<Vec<_>>>()?,
+        _ => args.regions,
+    };
+    let mask = match (args.mask.or(file_config.mask), regions.is_empty()) {
+        (Some(path), false) => Some(RegionMask { path, regions }),
+        (Some(_), true) => {
+            return Err(AppError::InvalidInput(
+                "a mask needs at least one region".to_string(),
+            ));
+        }
+        (None, false) => {
+            return Err(AppError::InvalidInput("regions need a mask image".to_string()));
+        }
+        (None, true) => None,
+    };
+
+    let pins = match (args.pins.is_empty(), file_config.pins) {
+        (true, Some(values)) => parse_all(&values)?,
+        _ => args.pins,
+    };
+    let bans = match (args.bans.is_empty(), file_config.bans) {
+        (true, Some(values)) => parse_all(&values)?,
+        _ => args.bans,
+    };
+
+    let detail_source = match (args.detail, file_config.detail) {
+        (Some(source), _) => Some(source),
+        (None, Some(value)) => Some(value.parse::<DetailSource>().map_err(AppError::InvalidInput)?),
+        (None, None) => None,
+    };
+    let detail = detail_source
+        .map(|source| {
+            let levels = args
+                .detail_levels
+                .or(file_config.detail_levels)
+                .unwrap_or(DetailSpec::DEFAULT_LEVELS);
+            if !(1..=8).contains(&levels) {
+                return Err(AppError::InvalidInput(format!(
+                    "detail levels must be between 1 and 8, got {levels}"
+                )));
+            }
+            let tolerance = args.detail_tolerance.or(file_config.detail_tolerance);
+            if tolerance.is_some_and(|tolerance| tolerance.is_nan() || tolerance < 0.0) {
+                return Err(AppError::InvalidInput(
+                    "detail tolerance must not be negative".to_string(),
+                ));
+            }
+            Ok(DetailSpec {
+                source,
+                levels,
+                tolerance,
+            })
+        })
+        .transpose()?;
+
+    let structure_weight = args.structure_weight.or(file_config.structure_weight);
+    if structure_weight.is_some_and(|weight| weight.is_nan() || weight < 0.0) {
+        return Err(AppError::InvalidInput(
+            "structure weight must not be negative".to_string(),
+        ));
+    }
+
+    let kernel = match (args.dither, file_config.dither) {
+        (Some(kernel), _) => Some(kernel),
+        (None, Some(value)) => Some(value.parse::<DitherKernel>().map_err(AppError::InvalidInput)?),
+        (None, None) => None,
+    };
+    let serpentine = args.serpentine || file_config.serpentine.unwrap_or(false);
+    let dither = match (kernel, serpentine) {
+        (Some(kernel), _) => Some(DitherSpec { kernel, serpentine }),
+        (None, true) => {
+            return Err(AppError::InvalidInput(
+                "serpentine order needs a dither kernel".to_string(),
+            ));
+        }
+        (None, false) => None,
+    };
+
+    let top_k = args.top_k.or(file_config.top_k);
+    let pick_tolerance = args.pick_tolerance.or(file_config.pick_tolerance);
+    let seed = args.seed.or(file_config.seed);
+    let pool = match (top_k, pick_tolerance) {
+        (Some(_), Some(_)) => {
+            return Err(AppError::InvalidInput(
+                "use either top-k or pick tolerance, not both".to_string(),
+            ));
+        }
+        (Some(0), None) => {
+            return Err(AppError::InvalidInput("top-k must be at least 1".to_string()));
+        }
+        (Some(k), None) => Some(SamplePool::Best(k)),
+        (None, Some(tolerance)) if tolerance.is_nan() || tolerance < 0.0 => {
+            return Err(AppError::InvalidInput(
+                "pick tolerance must not be negative".to_string(),
+            ));
+        }
+        (None, Some(tolerance)) => Some(SamplePool::Within(tolerance)),
+        (None, None) => None,
+    };
+    let sampling = match (pool, seed) {
+        (Some(pool), seed) => Some(SamplingSpec {
+            pool,
+            seed: seed.unwrap_or_else(random_seed),
+        }),
+        (None, Some(_)) => {
+            return Err(AppError::InvalidInput(
+                "a seed needs top-k or pick tolerance".to_string(),
+            ));
+        }
+        (None, None) => None,
+    };
+
+    let manifest = args.manifest.or(file_config.manifest);
+    if let Some(path) = &manifest {
+        // Fail before rendering rather than after.
+        infra::manifest::manifest_format(path)?;
+    }
+
     Ok(MosaicSpec {
         input,
         output,
         tile_size,
         tiles_source,
+        color_space: color_space(args.linear || file_config.linear.unwrap_or(false)),
+        metric,
+        matte,
+        preserve_transparency: args.transparent || file_config.transparent.unwrap_or(false),
+        memory_limit,
+        output_options,
+        mask,
+        detail,
+        structure_weight,
+        dither,
+        sampling,
+        pins,
+        bans,
+        manifest,
+        heatmap: args.heatmap.or(file_config.heatmap),
     })
 }
 
+/// A seed for runs that did not ask for one; it is printed so they can be repeated.
+fn random_seed() -> u64 {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_nanos() as u64 ^ ((std::process::id() as u64) << 32)
+}
+
+fn parse_all<T: std::str::FromStr<Err = String>>(values: &[String]) -> AppResult<Vec<T>> {
+    values
+        .iter()
+        .map(|value| value.parse().map_err(AppError::InvalidInput))
+        .collect()
+}
+
+fn build_render_spec(args: RenderArgs) -> AppResult<RenderSpec> {
+    Ok(RenderSpec {
+        manifest: infra::manifest::read_manifest(&args.manifest)?,
+        output: args.output,
+        tile_size: args.tile_size,
+        grout: args.grout,
+        grout_color: args.grout_color,
+        output_options: output_options(args.encoding, &config::GenerateConfig::default())?,
+    })
+}
+
+fn output_options(
+    args: EncodingArgs,
+    file_config: &config::GenerateConfig,
+) -> AppResult<OutputOptions> {
+    let format = match (args.format, &file_config.format) {
+        (Some(format), _) => Some(format),
+        (None, Some(value)) => {
+            Some(cli::parse_image_format(value).map_err(AppError::InvalidInput)?)
+        }
+        (None, None) => None,
+    };
+    let png_filter = match (args.png_filter, &file_config.png_filter) {
+        (Some(filter), _) => Some(filter),
+        (None, Some(value)) => Some(value.parse().map_err(AppError::InvalidInput)?),
+        (None, None) => None,
+    };
+
+    // Config values skip the ranges clap checks on the command line.
+    let jpeg_quality = args.jpeg_quality.or(file_config.jpeg_quality);
+    let png_compression = args.png_compression.or(file_config.png_compression);
+    let dpi = args.dpi.or(file_config.dpi);
+    Ok(OutputOptions {
+        format,
+        jpeg_quality: check_range("jpeg quality", jpeg_quality, 1..=100)?,
+        png_compression: check_range("png compression", png_compression, 0..=9)?,
+        png_filter,
+        dpi: check_range("dpi", dpi, 1..=65535)?,
+    })
+}
+
+fn check_range<T: PartialOrd + std::fmt::Display>(
+    name: &str,
+    value: Option<T>,
+    range: RangeInclusive<T>,
+) -> AppResult<Option<T>> {
+    match value {
+        Some(value) if !range.contains(&value) => Err(AppError::InvalidInput(format!(
+            "{name} must be between {} and {}, got {value}",
+            range.start(),
+            range.end()
+        ))),
+        value => Ok(value),
+    }
+}
+
+fn color_space(linear: bool) -> ColorSpace {
+    if linear {
+        ColorSpace::Linear
+    } else {
+        ColorSpace::Srgb
+    }
+}
+
 fn resolve_tiles_source(value: Option<String>) -> AppResult<TilesSource> {
     match value {
         None => Ok(TilesSource::Catalog),
@@ -1,5 +1,7 @@
 use crate::app::App;
-use crate::domain::{MosaicSpec, TilesSource};
+use crate::domain::{
+    ColorSpace, ImportOptions, MatchMetric, MosaicSpec, OutputOptions, TilesSource,
+};
 use crate::error::AppResult;
 use crate::infra::{ImageIoImpl, TomlCatalogStore};
 use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
@@ -96,13 +98,10 @@ pub fn run_tui(app: App<TomlCatalogStore, ImageIoImpl>, default_tile_size: u32)
         loop {
             terminal.draw(|frame| render(frame, &state, &menu_items))?;
 
-            match event::read()? {
-                Event::Key(key) => {
-                    if handle_key_event(key, &mut state, &menu_items, &app)? {
-                        break;
-                    }
-                }
-                _ => {}
+            if let Event::Key(key) = event::read()?
+                && handle_key_event(key, &mut state, &menu_items, &app)?
+            {
+                break;
             }
         }
         Ok(())
@@ -170,9 +169,10 @@ fn render_footer(state: &UiState) -> Paragraph<'static> {
         InputMode::GenerateTileSize => "Tile size (blank=default): ",
     };
 
-    let mut lines = Vec::new();
-    lines.push(Line::from(Span::raw(prompt.to_string())));
-    lines.push(Line::from(Span::raw(state.input_buffer.clone())));
+    let lines = vec![
+        Line::from(Span::raw(prompt.to_string())),
+        Line::from(Span::raw(state.input_buffer.clone())),
+    ];
 
     Paragraph::new(lines)
         .block(Block::default().borders(Borders::ALL).title("Input"))
@@ -198,15 +198,11 @@ fn handle_key_event(
     }
 
     match key.code {
-        KeyCode::Up => {
-            if state.selected > 0 {
-                state.selected -= 1;
-            }
+        KeyCode::Up if state.selected > 0 => {
+            state.selected -= 1;
         }
-        KeyCode::Down => {
-            if state.selected + 1 < menu_items.len() {
-                state.selected += 1;
-            }
+        KeyCode::Down if state.selected + 1 < menu_items.len() => {
+            state.selected += 1;
         }
         KeyCode::Enter => {
             let menu = menu_items[state.selected];
@@ -272,8 +268,9 @@ fn handle_input_mode(
                     if input.is_empty() {
                         state.status = vec!["Path is required.".to_string()];
                     } else {
-                        match app.catalog_add(&PathBuf::from(input)) {
-                            Ok(added) => {
+                        match app.catalog_add(&PathBuf::from(input), &ImportOptions::default()) {
+                            Ok(report) => {
+                                let added = report.added;
                                 state.status.clear();
                                 if added.is_empty() {
                                     state.status.push("No new tiles added.".to_string());
@@ -413,6 +410,21 @@ fn build_spec_from_form(state: &UiState) -> AppResult<MosaicSpec> {
         output,
         tile_size,
         tiles_source,
+        color_space: ColorSpace::default(),
+        metric: MatchMetric::default(),
+        matte: [0, 0, 0],
+        preserve_transparency: false,
+        memory_limit: None,
+        output_options: OutputOptions::default(),
+        mask: None,
+        detail: None,
+        structure_weight: None,
+        dither: None,
+        sampling: None,
+        pins: Vec::new(),
+        bans: Vec::new(),
+        manifest: None,
+        heatmap: None,
     })
 }
 